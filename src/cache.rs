@@ -0,0 +1,94 @@
+use crate::confluence::ConfluenceClient;
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, instrument, warn, Level};
+
+// Links contain the title of the page, so links cached in a previous run expire to pick up changes made in Confluence.
+const LINK_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+// ###################################################### //
+//                   Cache File Struct                    //
+// ###################################################### //
+
+/// Page links of each Confluence instance, persisted between runs to avoid looking up the same `pid:` links.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CacheFile {
+    links: HashMap<String, HashMap<String, CachedLink>>,
+}
+
+/// A page link and when it was looked up, in seconds since the Unix epoch.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CachedLink {
+    /// The URL of the page.
+    pub link: String,
+    /// When the link was looked up, in seconds since the Unix epoch.
+    pub cached_at: u64,
+}
+
+impl CachedLink {
+    /// A link looked up now.
+    pub fn new(link: &str) -> Self {
+        Self {
+            link: link.to_string(),
+            cached_at: unix_time(),
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        unix_time().saturating_sub(self.cached_at) > LINK_MAX_AGE.as_secs()
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default()
+}
+
+impl CacheFile {
+    /// A missing or unreadable cache file results in an empty cache.
+    #[instrument(ret(level = Level::TRACE))]
+    pub fn load(path: &str) -> Self {
+        if !Path::new(path).is_file() {
+            debug!("cache file not found, starting with an empty cache.");
+            return Self::default();
+        }
+
+        let read_cache = || -> Result<Self> {
+            let file = std::fs::read_to_string(path)?;
+            Ok(serde_json::from_str(&file)?)
+        };
+
+        read_cache().unwrap_or_else(|error| {
+            warn!(%error, "could not read cache file, starting with an empty cache.");
+            Self::default()
+        })
+    }
+
+    /// Adds the cached links of the file to the clients, skipping expired links.
+    pub fn restore(&mut self, clients: &HashMap<String, ConfluenceClient>) {
+        for client in clients.values() {
+            if let Some(mut links) = self.links.remove(&client.cache_key()) {
+                links.retain(|_, x| !x.is_expired());
+                client.extend_cached_links(links);
+            }
+        }
+    }
+
+    /// Writes the links of the clients to the file, keeping the links of instances not used in this run.
+    #[instrument(skip(self, clients))]
+    pub fn save(mut self, path: &str, clients: &HashMap<String, ConfluenceClient>) -> Result<()> {
+        for client in clients.values() {
+            self.links.insert(client.cache_key(), client.cached_links());
+        }
+
+        std::fs::write(path, serde_json::to_string_pretty(&self)?)?;
+        debug!("saved cache file.");
+
+        Ok(())
+    }
+}
@@ -1,44 +1,87 @@
-use crate::confluence;
+use crate::cache::CacheFile;
+use crate::confluence::{self, AuthMethod, ClientOptions, ConfluenceClient, Flavor, LabelMode};
 use crate::error::{Error, Result};
+use crate::filter::{self, PageFilter};
+use crate::git;
+use crate::interpolate;
+use crate::prune::PruneMode;
 use crate::render_markdown::HtmlPage;
-use crate::CommandArgs;
+use crate::secret::SecretSource;
+use crate::template;
 use derive_more::Debug;
-use serde::Deserialize;
+use normalize_path::NormalizePath;
+use regex::Regex;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
-use tracing::{instrument, span, Level};
+use std::path::{Path, PathBuf};
+use tracing::{debug, error, instrument, span, Level};
 
 // ###################################################### //
 //                     Config Struct                      //
 // ###################################################### //
 
+/// The loaded config files, with a client for each instance and the rendered pages to update.
 #[derive(Debug)]
 pub struct Config {
-    pub user: String,
-    #[debug("\"<redacted>\"")]
-    pub secret: String,
-    pub fqdn: String,
+    /// A client for each instance, by instance name.
+    pub clients: HashMap<String, ConfluenceClient>,
+    /// The pages selected by the filters.
     pub pages: Vec<Page>,
+    /// Where pages removed from the config are pruned, empty unless pruning.
+    pub prune_scopes: Vec<PruneScope>,
+    /// The restored cache file, if a cache file is used.
+    pub cache: Option<CacheFile>,
 }
 
+/// Pages without an instance use the instance given by the command line arguments.
+pub const DEFAULT_INSTANCE: &str = "default";
+
+/// A rendered page, ready to be published with [`ConfluenceClient::update_confluence_page`].
 #[derive(Debug)]
 pub struct Page {
+    /// Name of the instance the page is published to.
+    pub instance: String,
+    /// Path to the Markdown file.
     pub file_path: String,
+    /// ID of the Confluence page, empty until a new page is created.
     pub page_id: String,
+    /// Set if the page doesn't exist yet and is created when updated.
+    pub new_page: Option<NewPage>,
+    /// ID of the page this page should be a child of.
+    pub parent_id: Option<String>,
+    /// Title of the page.
     pub title: String,
+    /// Labels of the page, with invalid labels handled by the label mode.
     pub labels: Vec<String>,
+    /// Restrict editing of the page to the token owner.
     pub read_only: Option<bool>,
+    /// Users and groups allowed to view and edit the page.
+    pub restrictions: Option<RestrictionsConfig>,
+    /// The superscript header added to the top of the page.
     #[allow(dead_code)]
     pub superscript_header: Option<String>,
+    /// The rendered page.
     pub html: HtmlPage,
+    /// Hash of the page content and settings, pages with an unchanged SHA are skipped.
     pub page_sha: String,
+    /// The version message of the update.
+    pub version_message: Option<String>,
+    /// Owner of the config file the page is published from.
+    pub owner: Option<String>,
 }
 
 impl Page {
+    /// Renders the page, resolving `pid:` links and looking up pages without a page ID with the client.
     #[instrument(skip_all, ret(level = Level::TRACE), err(Display))]
-    async fn try_from_async(page_config: PageConfig) -> Result<Self> {
-        let html = HtmlPage::new(&page_config).await?;
+    pub async fn try_from_async(
+        page_config: PageConfig,
+        client: &ConfluenceClient,
+        version_message: Option<String>,
+    ) -> Result<Self> {
+        let html = HtmlPage::new(&page_config, client).await?;
 
         let title = match (&page_config.override_title, &html.page_header) {
             (Some(override_title), _) => override_title,
@@ -46,73 +89,317 @@ impl Page {
             (None, None) => return Err(Error::PageHeaderMissing),
         };
 
+        // Pages without a page id are looked up by space and the explicit title, never by the h1 header, so
+        // editing the header doesn't create another page. Missing pages are created by the update.
+        let (page_id, new_page) = match (
+            &page_config.page_id,
+            &page_config.space,
+            &page_config.override_title,
+        ) {
+            (Some(page_id), _, _) => (page_id.to_string(), None),
+            (None, Some(space), Some(title)) => {
+                let parent_id = match &page_config.parent {
+                    Some(parent) => Some(client.find_parent_id(space, parent).await?),
+                    None => None,
+                };
+
+                match client
+                    .find_unique_page(space, title, parent_id.as_deref())
+                    .await?
+                {
+                    Some(page_id) => (page_id, None),
+                    None if page_config.create_missing.unwrap_or(false) => {
+                        let new_page = NewPage {
+                            space_key: space.to_string(),
+                            parent_id,
+                        };
+                        (String::new(), Some(new_page))
+                    }
+                    None => return Err(Error::PageNotFound(format!("{title} in space {space}"))),
+                }
+            }
+            (None, Some(_), None) => return Err(Error::PageTitleMissing(page_config.file_path)),
+            (None, None, _) => return Err(Error::PageIdMissing(page_config.file_path)),
+        };
+
         let labels = match &page_config.labels {
             Some(labels) => labels.to_owned(),
             None => vec![],
         };
 
-        let page_sha = page_config.calculate_sha()?;
+        let page_sha = page_config.calculate_sha(&html)?;
 
         let page = Self {
+            instance: page_config.instance_name().to_string(),
             file_path: page_config.file_path,
-            page_id: page_config.page_id,
+            page_id,
+            new_page,
+            parent_id: page_config.parent_id,
             title: title.to_string(),
             labels,
             read_only: page_config.read_only,
+            restrictions: page_config.restrictions,
             superscript_header: page_config.superscript_header,
             html,
             page_sha,
+            version_message,
+            owner: page_config.owner,
         };
 
         Ok(page)
     }
 }
 
+/// A page that doesn't exist yet, created in the space below the parent page when the page is updated.
+#[derive(Debug, Clone)]
+pub struct NewPage {
+    /// Key of the space the page is created in.
+    pub space_key: String,
+    /// ID of the parent page, the page is created at the top of the space if not set.
+    pub parent_id: Option<String>,
+}
+
 // ###################################################### //
 //                  ConfigFile Struct                     //
 // ###################################################### //
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+/// Configuration file for confluence-updater.
+#[derive(Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct ConfigFile {
-    pages: Vec<PageConfig>,
+    /// Path of the config file, set when loaded.
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub path: String,
+    /// Other config files to load, relative to this config file.
+    include: Option<Vec<String>>,
+    /// The Markdown files to render and the Confluence pages to update.
+    #[serde(default)]
+    pub pages: Vec<PageConfig>,
+    /// Named Confluence instances that pages can be published to.
+    pub instances: Option<HashMap<String, InstanceConfig>>,
+    /// Restrict editing of all pages to the token owner. If omitted, page restrictions are not modified.
     read_only: Option<bool>,
+    /// Users and groups allowed to view and edit all pages. Takes precedence over readOnly.
+    restrictions: Option<RestrictionsConfig>,
+    /// Create pages identified by space and title if they don't exist.
+    create_missing: Option<bool>,
+    /// How invalid labels are handled: skipped, normalized or failing the run. Defaults to filter.
+    label_mode: Option<LabelMode>,
+    /// The space, and optionally the parent page, where pages removed from the config are pruned with `--prune`.
+    prune: Option<PruneScope>,
+    /// Identifies the pages published by this config file, pruning only removes pages with the same owner.
+    /// Defaults to the URL of the `origin` remote of the git repository and the path of the config file in it.
+    owner: Option<String>,
+    /// A superscript header with Markdown support added to the top of all pages.
     superscript_header: Option<String>,
+    /// A notice showing the source file and commit of all pages.
+    source_notice: Option<SourceNotice>,
+    /// Variables substituted into the Markdown of all pages using the `{{ name }}` syntax.
+    #[serde(default)]
+    #[schemars(schema_with = "variables_schema")]
+    variables: Option<HashMap<String, String>>,
 }
 
 impl ConfigFile {
+    /// Parses the YAML, TOML or JSON config file, without loading its includes.
     #[instrument(skip_all, ret(level = Level::TRACE), err(Display))]
     pub fn new(path: &str) -> Result<ConfigFile> {
         if !Path::new(path).is_file() {
             return Err(Error::InvalidFilePath(path.to_string()));
         }
         let file = fs::read_to_string(path)?;
-        let yaml: ConfigFile = serde_yml::from_str(&file)?;
-        Ok(yaml)
+
+        // TOML and JSON are converted to YAML, so all formats share the same interpolation and parsing.
+        let format = match Path::new(path).extension().and_then(|x| x.to_str()) {
+            Some("toml") => "TOML",
+            Some("json") => "JSON",
+            _ => "YAML",
+        };
+
+        let yaml = match format {
+            "TOML" => serde_yml::to_string(&toml::from_str::<serde_yml::Value>(&file)?)?,
+            "JSON" => serde_yml::to_string(
+                &serde_json::from_str::<serde_yml::Value>(&file).map_err(Error::Json)?,
+            )?,
+            _ => file,
+        };
+
+        let yaml = interpolate::interpolate_yaml(&yaml)?;
+
+        // All formats are deserialized from YAML, so numbers and booleans are accepted for strings like page IDs.
+        // Line numbers of the converted YAML don't match the TOML or JSON file, so those are left out of errors.
+        let config_file = serde_yml::from_str(&yaml).map_err(|error| match format {
+            "YAML" => error,
+            _ => without_location(error),
+        });
+
+        let mut config_file: ConfigFile =
+            config_file.map_err(|error| unknown_field_suggestion(format, error))?;
+        config_file.path = path.to_string();
+
+        Ok(config_file)
+    }
+
+    /// Loads the config files and all config files they include, in order. Each file is only loaded once.
+    #[instrument(skip_all, err(Display))]
+    pub fn load_all(paths: &[String]) -> Result<Vec<ConfigFile>> {
+        let mut config_files = vec![];
+        let mut stack = vec![];
+
+        for path in paths {
+            load_config_file(Path::new(path), &mut stack, &mut config_files)?;
+        }
+
+        Ok(config_files)
+    }
+
+    /// Takes the instances of all config files. An instance can only be defined once.
+    pub fn merge_instances(
+        config_files: &mut [ConfigFile],
+    ) -> Result<HashMap<String, InstanceConfig>> {
+        let mut instances = HashMap::new();
+
+        for config_file in config_files.iter_mut() {
+            for (name, instance) in config_file.instances.take().unwrap_or_default() {
+                if instances.insert(name.to_string(), instance).is_some() {
+                    return Err(Error::DuplicateInstance(name));
+                }
+            }
+        }
+
+        Ok(instances)
+    }
+
+    /// Applies the global properties and command line labels to a page, unless explicitly set on the page.
+    pub fn apply_defaults(
+        &self,
+        page_config: &mut PageConfig,
+        cli_labels: &[String],
+        cli_label_mode: Option<LabelMode>,
+    ) {
+        // Overwrite superscript_header if it's set globally and not explicitly on the page.
+        if self.superscript_header.is_some() && page_config.superscript_header.is_none() {
+            page_config.superscript_header = self.superscript_header.clone();
+        }
+
+        // Overwrite source_notice if it's set globally and not explicitly on the page.
+        if page_config.source_notice.is_none() {
+            page_config.source_notice = self.source_notice.clone();
+        }
+
+        page_config.owner = self.owner.clone();
+
+        // Merge page variables with global and environment variables. Substitution is enabled by any of them.
+        let env_variables = env_variables();
+        if !env_variables.is_empty() || self.variables.is_some() || page_config.variables.is_some()
+        {
+            let mut variables = env_variables;
+            variables.extend(self.variables.clone().unwrap_or_default());
+            variables.extend(page_config.variables.take().unwrap_or_default());
+            page_config.variables = Some(variables);
+        }
+
+        // If there are not labes on the page config, create an empty vec.
+        if page_config.labels.is_none() {
+            page_config.labels = Some(vec![]);
+        }
+
+        // Add global cli labels to the page config.
+        if let Some(ref mut vec) = page_config.labels {
+            vec.extend(cli_labels.iter().cloned());
+        }
+
+        // The command line label mode takes precedence over the one in the config file.
+        page_config.label_mode = cli_label_mode.or(self.label_mode).unwrap_or_default();
+
+        // Overwrite create_missing if it's set globally and not explicitly on the page.
+        if page_config.create_missing.is_none() {
+            page_config.create_missing = self.create_missing;
+        }
+
+        // Overwrite restrictions if they're set globally and not explicitly on the page.
+        if page_config.restrictions.is_none() {
+            page_config.restrictions = self.restrictions.clone();
+        }
+
+        // Set default read_only to true or overwrite read_only if it's set globally and not explicitly on the page.
+        page_config.read_only = match (self.read_only, page_config.read_only) {
+            (None, None) => None,
+            (_, Some(page)) => Some(page),
+            (Some(config), None) => Some(config),
+        };
     }
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+/// A Markdown file and the Confluence page it's published to.
+#[derive(Deserialize, JsonSchema, Debug, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct PageConfig {
+    /// Path to the Markdown file.
     pub file_path: String,
-    pub page_id: String,
+    /// ID of the Confluence page to update. Required unless the page is identified by space and title.
+    #[serde(default)]
+    #[schemars(schema_with = "optional_string_or_number_schema")]
+    pub page_id: Option<String>,
+    /// Key of the space to look up the page in by its title, if no page ID is set. Requires a title.
+    pub space: Option<String>,
+    /// Title of the parent page, used when looking up and creating the page by space and title.
+    pub parent: Option<String>,
+    /// ID of the page this page should be a child of. The page is moved if it has another parent.
+    #[serde(default)]
+    #[schemars(schema_with = "optional_string_or_number_schema")]
+    pub parent_id: Option<String>,
+    /// Create the page in the space if no page with the title exists. Overrides the global property.
+    pub create_missing: Option<bool>,
+    /// Name of the instance to publish the page to. Defaults to the command line instance.
+    pub instance: Option<String>,
+    /// Tags used for filtering pages with `--tag`.
+    pub tags: Option<Vec<String>>,
+    /// Page title to use instead of the h1 header. Required to look up the page by space and title.
+    #[serde(alias = "title")]
     pub override_title: Option<String>,
+    /// Labels to add to the page.
     pub labels: Option<Vec<String>>,
+    /// Label mode of the config file or command line, set when the defaults are applied.
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub label_mode: LabelMode,
+    /// Restrict editing of the page to the token owner. Overrides the global property.
     pub read_only: Option<bool>,
+    /// Users and groups allowed to view and edit the page. Takes precedence over readOnly. Overrides the global property.
+    pub restrictions: Option<RestrictionsConfig>,
+    /// A superscript header added to the top of the page. Overrides the global property.
     pub superscript_header: Option<String>,
+    /// A notice showing the source file and commit of the page. Overrides the global property.
+    pub source_notice: Option<SourceNotice>,
+    /// Variables substituted into the Markdown using the `{{ name }}` syntax. Merged with the global variables.
+    #[serde(default)]
+    #[schemars(schema_with = "variables_schema")]
+    pub variables: Option<HashMap<String, String>>,
+    /// Owner of the config file, set when the defaults are applied.
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub owner: Option<String>,
 }
 
 impl PageConfig {
+    /// Name of the instance the page is published to.
+    pub fn instance_name(&self) -> &str {
+        self.instance.as_deref().unwrap_or(DEFAULT_INSTANCE)
+    }
+
     #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
-    fn calculate_sha(&self) -> Result<String> {
+    fn calculate_sha(&self, html: &HtmlPage) -> Result<String> {
         if !Path::new(&self.file_path).is_file() {
             return Err(Error::InvalidFilePath(self.file_path.to_string()));
         }
 
-        let mut content = std::fs::read_to_string(&self.file_path)?;
+        // The processed markdown is used, so changes to variables also change the SHA.
+        let mut content = html.markdown.to_string();
         content.push_str(self.override_title.as_ref().unwrap_or(&"".to_string()));
         content.push_str(self.superscript_header.as_ref().unwrap_or(&"".to_string()));
+        content.push_str(html.source_notice.as_ref().unwrap_or(&"".to_string()));
 
         let restrictions = match self.read_only {
             Some(true) => "true",
@@ -122,6 +409,16 @@ impl PageConfig {
 
         content.push_str(restrictions);
 
+        // Pages are updated once when their owner changes, so the owner property is set on all pages.
+        if let Some(owner) = &self.owner {
+            content.push_str(owner);
+        }
+
+        // Only added when set, so the SHA of pages without restrictions is unchanged.
+        if let Some(restrictions) = &self.restrictions {
+            content.push_str(&serde_json::to_string(restrictions)?);
+        }
+
         if let Some(vec) = &self.labels {
             content.push_str(&vec.join(""));
         }
@@ -135,62 +432,585 @@ impl PageConfig {
     }
 }
 
+// Loads a config file and the config files it includes. The file paths of pages are relative to the config
+// file they're defined in.
+fn load_config_file(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    config_files: &mut Vec<ConfigFile>,
+) -> Result<()> {
+    let normalized_path = path.normalize();
+
+    if stack.contains(&normalized_path) {
+        let chain = stack
+            .iter()
+            .chain([&normalized_path])
+            .map(|x| x.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        return Err(Error::IncludeCycle(chain));
+    }
+
+    if config_files
+        .iter()
+        .any(|x| Path::new(&x.path).normalize() == normalized_path)
+    {
+        debug!(path = %path.display(), "config file already loaded, skipping.");
+        return Ok(());
+    }
+
+    let path_string = path.display().to_string();
+    let mut config_file =
+        ConfigFile::new(&path_string).map_err(|error| Error::InvalidConfigFile {
+            path: path_string.to_string(),
+            source: Box::new(error),
+        })?;
+
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    for page_config in config_file.pages.iter_mut() {
+        let file_path = base_dir.join(&page_config.file_path).normalize();
+        page_config.file_path = file_path.display().to_string();
+    }
+
+    let includes = config_file.include.take().unwrap_or_default();
+
+    stack.push(normalized_path);
+    config_files.push(config_file);
+
+    for include in includes {
+        load_config_file(&base_dir.join(include), stack, config_files)?;
+    }
+
+    stack.pop();
+
+    Ok(())
+}
+
+// YAML numbers are accepted for string properties like page IDs.
+fn optional_string_or_number_schema(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({ "type": ["string", "integer", "null"] })
+}
+
+fn variables_schema(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": ["object", "null"],
+        "additionalProperties": { "type": ["string", "number", "boolean"] }
+    })
+}
+
+/// Returns the JSON schema of the config file.
+pub fn json_schema() -> Result<String> {
+    let schema = schemars::schema_for!(ConfigFile);
+    Ok(serde_json::to_string_pretty(&schema)?)
+}
+
+fn without_location(error: serde_yml::Error) -> serde_yml::Error {
+    let regex = Regex::new(r" at line \d+ column \d+$").expect("invalid regex patteren");
+    serde::de::Error::custom(regex.replace(&error.to_string(), ""))
+}
+
+// Adds a suggestion for the closest known field to unknown field errors, e.g. `readonly` -> `readOnly`.
+fn unknown_field_suggestion(format: &'static str, error: serde_yml::Error) -> Error {
+    let regex =
+        Regex::new(r"unknown field `([^`]+)`, expected (.*)").expect("invalid regex patteren");
+    let message = error.to_string();
+
+    let Some(captures) = regex.captures(&message) else {
+        return Error::ConfigContent(format, error);
+    };
+
+    let field = &captures[1];
+    let candidates = captures[2].split('`').skip(1).step_by(2);
+
+    let closest = candidates
+        .map(|x| (edit_distance(&field.to_lowercase(), &x.to_lowercase()), x))
+        .filter(|(distance, x)| *distance <= (x.len() / 3).max(2))
+        .min_by_key(|(distance, _)| *distance);
+
+    match closest {
+        Some((_, suggestion)) => Error::UnknownConfigField(format, error, suggestion.to_string()),
+        None => Error::ConfigContent(format, error),
+    }
+}
+
+// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let cost = usize::from(a_char != *b_char);
+            current.push(
+                (previous[j] + cost)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
 // ###################################################### //
-//             TryFrom CommandArgs -> Config              //
+//                 InstanceConfig Struct                  //
+// ###################################################### //
+
+/// A named Confluence instance and how to authenticate with it.
+#[derive(Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct InstanceConfig {
+    /// The fully qualified domain name, including the protocol scheme.
+    pub fqdn: String,
+    /// The Confluence flavor. Defaults to cloud.
+    pub flavor: Option<Flavor>,
+    /// The context path Confluence is served from.
+    pub context_path: Option<String>,
+    /// The authentication method. Defaults to basic for Cloud and bearer for Data Center.
+    pub auth: Option<AuthMethod>,
+    /// The user to login with. Required for basic authentication.
+    pub user: Option<String>,
+    /// Name of the environment variable containing the secret.
+    pub secret_env: Option<String>,
+    /// Path to a file containing the secret.
+    pub secret_file: Option<String>,
+    /// A git credential helper returning the secret.
+    pub credential_helper: Option<String>,
+    /// The OAuth 2.0 client id. Required for OAuth authentication.
+    pub oauth_client_id: Option<String>,
+    /// Name of the environment variable containing an OAuth 2.0 refresh token.
+    pub oauth_refresh_token_env: Option<String>,
+    /// Path to a file containing an OAuth 2.0 refresh token. Rotated refresh tokens are written back to it.
+    pub oauth_refresh_token_file: Option<String>,
+    /// The cloud id of the Atlassian Cloud site, used for OAuth authentication.
+    pub cloud_id: Option<String>,
+}
+
+impl InstanceConfig {
+    fn secret_source(&self) -> Option<SecretSource> {
+        match (&self.secret_env, &self.secret_file, &self.credential_helper) {
+            (Some(name), _, _) => Some(SecretSource::Env(name.to_string())),
+            (None, Some(path), _) => Some(SecretSource::File(path.to_string())),
+            (None, None, Some(command)) => {
+                Some(SecretSource::CredentialHelper(command.to_string()))
+            }
+            (None, None, None) => None,
+        }
+    }
+
+    fn client_options(&self) -> Result<ClientOptions> {
+        let source = self.secret_source().ok_or(Error::SecretMissing)?;
+        let secret = source.resolve(&self.fqdn, self.user.as_deref())?;
+
+        let oauth_refresh_token = match &self.oauth_refresh_token_env {
+            Some(name) => Some(SecretSource::Env(name.to_string()).resolve(&self.fqdn, None)?),
+            None => None,
+        };
+
+        Ok(ClientOptions {
+            fqdn: self.fqdn.to_string(),
+            flavor: self.flavor.unwrap_or(Flavor::Cloud),
+            context_path: self.context_path.clone(),
+            auth_method: self.auth,
+            user: self.user.clone(),
+            secret,
+            oauth_client_id: self.oauth_client_id.clone(),
+            oauth_refresh_token,
+            oauth_refresh_token_file: self.oauth_refresh_token_file.clone(),
+            cloud_id: self.cloud_id.clone(),
+        })
+    }
+}
+
+// Takes the prune scopes of all config files. Pruning requires a scope and the owner of its config file.
+#[instrument(skip_all, err(Display))]
+fn take_prune_scopes(
+    config_files: &mut [ConfigFile],
+    prune_mode: Option<PruneMode>,
+) -> Result<Vec<PruneScope>> {
+    let mut prune_scopes = vec![];
+
+    for config_file in config_files.iter_mut() {
+        if let Some(mut prune_scope) = config_file.prune.take() {
+            prune_scope.owner = config_file.owner.clone();
+            prune_scopes.push(prune_scope);
+        }
+    }
+
+    if prune_mode.is_some() && prune_scopes.is_empty() {
+        return Err(Error::PruneScopeMissing);
+    }
+
+    if prune_mode.is_some() && prune_scopes.iter().any(|x| x.owner.is_none()) {
+        return Err(Error::PruneOwnerMissing);
+    }
+
+    Ok(prune_scopes)
+}
+
+// Creates a client for each instance used by the pages or prune scopes.
+#[instrument(skip_all, err(Display))]
+async fn create_clients(
+    default_instance: Option<&DefaultInstance>,
+    instances: &HashMap<String, InstanceConfig>,
+    instance_names: HashSet<&str>,
+) -> Result<HashMap<String, ConfluenceClient>> {
+    if instances.contains_key(DEFAULT_INSTANCE) {
+        return Err(Error::InvalidInstance(DEFAULT_INSTANCE.to_string()));
+    }
+
+    let mut clients = HashMap::new();
+
+    for name in instance_names {
+        let client_options = match (name, instances.get(name)) {
+            (DEFAULT_INSTANCE, _) => default_instance
+                .ok_or(Error::FqdnMissing)?
+                .client_options()?,
+            (_, Some(instance)) => instance.client_options()?,
+            (_, None) => return Err(Error::UnknownInstance(name.to_string())),
+        };
+
+        debug!(instance = name, "creating client for instance");
+        let client = ConfluenceClient::connect(&client_options).await?;
+        clients.insert(name.to_string(), client);
+    }
+
+    Ok(clients)
+}
+
+// ###################################################### //
+//               RestrictionsConfig Struct                //
+// ###################################################### //
+
+/// Users and groups allowed to view and edit a page.
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RestrictionsConfig {
+    /// Users and groups allowed to view the page. If omitted, anyone with access to the space can view the page.
+    pub view: Option<Principals>,
+    /// Users and groups allowed to edit the page. If omitted, anyone who can view the page can edit it.
+    pub edit: Option<Principals>,
+}
+
+/// Users and groups granted a restriction.
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Principals {
+    /// Account IDs on Confluence Cloud, usernames on Confluence Data Center.
+    #[serde(default)]
+    pub users: Vec<String>,
+    /// Group names.
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+// ###################################################### //
+//                   PruneScope Struct                    //
+// ###################################################### //
+
+/// Where managed pages removed from the config are pruned.
+#[derive(Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PruneScope {
+    /// Key of the space to prune pages in.
+    pub space: String,
+    /// Only prune pages below this page.
+    #[serde(default)]
+    #[schemars(schema_with = "optional_string_or_number_schema")]
+    pub parent_id: Option<String>,
+    /// Name of the instance to prune pages in. Defaults to the command line instance.
+    pub instance: Option<String>,
+    /// Owner of the config file, only pages with this owner are pruned.
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub owner: Option<String>,
+}
+
+impl PruneScope {
+    /// Name of the instance to prune pages in.
+    pub fn instance_name(&self) -> &str {
+        self.instance.as_deref().unwrap_or(DEFAULT_INSTANCE)
+    }
+}
+
+// ###################################################### //
+//                  SourceNotice Struct                   //
+// ###################################################### //
+
+/// A notice linking to the source file of a page.
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SourceNotice {
+    /// The notice text with Markdown support. Supports the `{{repo_url}}`, `{{file_path}}`, `{{commit}}`, `{{commit_date}}` and `{{author}}` placeholders.
+    pub template: String,
+    /// URL of the repository. Defaults to the URL of the `origin` remote.
+    pub repo_url: Option<String>,
+    /// Where on the page the notice is added.
+    #[serde(default)]
+    pub position: NoticePosition,
+    /// How the notice is rendered.
+    #[serde(default)]
+    pub style: NoticeStyle,
+}
+
+/// Where on the page the source notice is added.
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum NoticePosition {
+    /// At the top of the page.
+    #[default]
+    Header,
+    /// At the bottom of the page.
+    Footer,
+}
+
+/// How the source notice is rendered.
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum NoticeStyle {
+    /// Superscript text.
+    #[default]
+    Superscript,
+    /// A note panel.
+    Note,
+    /// An info panel.
+    Info,
+}
+
+impl SourceNotice {
+    /// Fills the template placeholders with Git metadata of the given markdown file.
+    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    pub fn render_text(&self, file_path: &str) -> Result<String> {
+        let mut variables = HashMap::new();
+
+        // Metadata that can't be read is left out, only failing the render if the template uses it.
+        match &self.repo_url {
+            Some(url) => variables.insert("repo_url", Ok(url.trim_end_matches('/').to_string())),
+            None => variables.insert("repo_url", git::remote_url()),
+        };
+
+        variables.insert("file_path", git::repo_relative_path(file_path));
+
+        match git::Commit::last_for_path(file_path) {
+            Ok(commit) => {
+                variables.insert("commit", Ok(commit.sha));
+                variables.insert("commit_date", Ok(commit.date));
+                variables.insert("author", Ok(commit.author));
+            }
+            Err(error) => debug!(%error, "could not read commit for source notice"),
+        }
+
+        let variables = variables
+            .into_iter()
+            .filter_map(|(name, value)| match value {
+                Ok(value) => Some((name.to_string(), value)),
+                Err(error) => {
+                    debug!(name, %error, "could not read source notice variable");
+                    None
+                }
+            })
+            .collect();
+
+        template::render(&self.template, &variables)
+    }
+}
+
+// ###################################################### //
+//                 DefaultInstance Struct                 //
+// ###################################################### //
+
+/// The instance used by pages without an instance, given by the command line arguments.
+#[derive(Debug, Default)]
+pub struct DefaultInstance {
+    /// The URL of the instance, required once a page uses the instance.
+    pub fqdn: Option<String>,
+    /// The kind of Confluence instance.
+    pub flavor: Flavor,
+    /// The path Confluence is served from.
+    pub context_path: Option<String>,
+    /// The authentication method, defaults to the method of the flavor.
+    pub auth_method: Option<AuthMethod>,
+    /// The user to login with.
+    pub user: Option<String>,
+    /// Where the secret is read from, required once a page uses the instance.
+    pub secret_source: Option<SecretSource>,
+    /// The OAuth 2.0 client id.
+    pub oauth_client_id: Option<String>,
+    /// The OAuth 2.0 refresh token.
+    #[debug("{:?}", oauth_refresh_token.as_ref().map(|_| "<redacted>"))]
+    pub oauth_refresh_token: Option<String>,
+    /// Path to a file containing the OAuth 2.0 refresh token.
+    pub oauth_refresh_token_file: Option<String>,
+    /// The cloud id of the Atlassian Cloud site.
+    pub cloud_id: Option<String>,
+}
+
+impl DefaultInstance {
+    /// The secret is resolved here, so it's only read when the instance is used.
+    pub fn client_options(&self) -> Result<ClientOptions> {
+        let fqdn = self.fqdn.clone().ok_or(Error::FqdnMissing)?;
+        let source = self.secret_source.as_ref().ok_or(Error::SecretMissing)?;
+        let secret = source.resolve(&fqdn, self.user.as_deref())?;
+
+        Ok(ClientOptions {
+            fqdn,
+            flavor: self.flavor,
+            context_path: self.context_path.clone(),
+            auth_method: self.auth_method,
+            user: self.user.clone(),
+            secret,
+            oauth_client_id: self.oauth_client_id.clone(),
+            oauth_refresh_token: self.oauth_refresh_token.clone(),
+            oauth_refresh_token_file: self.oauth_refresh_token_file.clone(),
+            cloud_id: self.cloud_id.clone(),
+        })
+    }
+}
+
+// ###################################################### //
+//                  ConfigOptions Struct                  //
+// ###################################################### //
+
+/// Options for loading the config files, given by the command line arguments.
+#[derive(Debug, Default)]
+pub struct ConfigOptions {
+    /// Paths of the config files to load.
+    pub config_paths: Vec<String>,
+    /// The instance used by pages without an instance.
+    pub default_instance: Option<DefaultInstance>,
+    /// Labels added to all pages.
+    pub labels: Vec<String>,
+    /// Overrides the label mode of the config files.
+    pub label_mode: Option<LabelMode>,
+    /// Only pages with one of these page IDs are updated.
+    pub page_filters: Vec<String>,
+    /// Only pages with a file path matching one of these glob patterns are updated.
+    pub file_filters: Vec<String>,
+    /// Only pages with one of these tags are updated.
+    pub tag_filters: Vec<String>,
+    /// Only pages with files changed since this Git ref are updated.
+    pub changed_since: Option<String>,
+    /// Update all pages if the changed files can't be read from Git.
+    pub changed_since_fallback: bool,
+    /// Loads the prune scopes of the config files to prune pages in this mode.
+    pub prune: Option<PruneMode>,
+    /// Restores page links from this cache file.
+    pub cache_file: Option<String>,
+    /// The version message of the updated pages, defaults to the current Git commit.
+    pub version_message: Option<String>,
+}
+
+// ###################################################### //
+//             TryFrom ConfigOptions -> Config            //
 // ###################################################### //
 
 impl Config {
+    /// Loads the config files, connects to the instances and renders the selected pages.
     #[instrument(skip_all, ret(level = Level::TRACE))]
-    pub async fn try_from_async(args: CommandArgs) -> Result<Self> {
-        let config_file = ConfigFile::new(&args.config_path)?;
+    pub async fn try_from_async(args: ConfigOptions) -> Result<Self> {
+        let mut config_files = ConfigFile::load_all(&args.config_paths)?;
+        let config_paths: Vec<String> = config_files.iter().map(|x| x.path.to_string()).collect();
+        let instances = ConfigFile::merge_instances(&mut config_files)?;
+
+        // Pages are marked with the owner of their config file, so pruning never removes pages of other configs.
+        // The default owner includes the path of the config file, since a repository can have many config files.
+        let remote_url = git::remote_url().ok();
+        for config_file in config_files.iter_mut() {
+            let default_owner = || {
+                let path = git::repo_relative_path(&config_file.path).ok()?;
+                Some(format!("{}#{path}", remote_url.as_ref()?))
+            };
+            config_file.owner = config_file.owner.take().or_else(default_owner);
+        }
+
+        let prune_scopes = take_prune_scopes(&mut config_files, args.prune)?;
+        let version_message = git::resolve_version_message(args.version_message.as_deref());
+
+        // Global properties only apply to the pages of their own config file.
+        let mut page_configs = vec![];
+        for config_file in config_files.iter_mut() {
+            for mut page_config in config_file.pages.drain(..).collect::<Vec<_>>() {
+                config_file.apply_defaults(&mut page_config, &args.labels, args.label_mode);
+                page_configs.push(page_config);
+            }
+        }
+
+        let filter = PageFilter::new(&args.page_filters, &args.file_filters, &args.tag_filters)?;
+        let mut page_configs = filter.apply(page_configs)?;
+
+        if let Some(git_ref) = &args.changed_since {
+            page_configs = filter::changed_since(
+                page_configs,
+                git_ref,
+                &config_paths,
+                args.changed_since_fallback,
+            )?;
+        }
+
+        let instance_names: HashSet<&str> = page_configs
+            .iter()
+            .map(|x| x.instance_name())
+            .chain(prune_scopes.iter().map(|x| x.instance_name()))
+            .collect();
+
+        let clients =
+            create_clients(args.default_instance.as_ref(), &instances, instance_names).await?;
+
+        // The cached links are restored before rendering, since `pid:` links are replaced when rendering.
+        let cache = args
+            .cache_file
+            .as_deref()
+            .map(CacheFile::load)
+            .map(|mut cache| {
+                cache.restore(&clients);
+                cache
+            });
 
         let mut pages: Vec<Page> = vec![];
 
-        for mut page_config in config_file.pages {
+        for mut page_config in page_configs {
             let span = span!(
                 Level::INFO,
                 "page",
+                instance = page_config.instance_name(),
                 id = page_config.page_id,
                 path = page_config.file_path
             );
 
             let _enter = span.enter();
 
-            // Overwrite superscript_header if it's set globally and not explicitly on the page.
-            if config_file.superscript_header.is_some() && page_config.superscript_header.is_none()
-            {
-                page_config.superscript_header = config_file.superscript_header.clone();
-            }
-
-            // If there are not labes on the page config, create an empty vec.
-            if page_config.labels.is_none() {
-                page_config.labels = Some(vec![]);
-            }
-
-            // Add global cli labels to the page config and filter our invalid labels.
+            // Filter out, normalize or fail on invalid labels.
             if let Some(ref mut vec) = page_config.labels {
-                vec.extend(args.labels.iter().cloned());
-                *vec = confluence::filter_valid_labels(vec);
+                *vec = confluence::apply_label_mode(vec, page_config.label_mode)
+                    .inspect_err(|error| error!(%error))?;
             }
 
-            // Set default read_only to true or overwrite read_only if it's set globally and not explicitly on the page.
-            page_config.read_only = match (config_file.read_only, page_config.read_only) {
-                (None, None) => None,
-                (_, Some(page)) => Some(page),
-                (Some(config), None) => Some(config),
-            };
-
-            let page = Page::try_from_async(page_config).await?;
+            let client = &clients[page_config.instance_name()];
+            let page = Page::try_from_async(page_config, client, version_message.clone()).await?;
             pages.push(page);
         }
 
         let config = Self {
-            user: args.user,
-            secret: args.secret,
-            fqdn: args.fqdn,
+            clients,
             pages,
+            prune_scopes,
+            cache,
         };
 
         Ok(config)
     }
 }
+
+// Environment variables prefixed with CU_VAR_ are available as template variables without the prefix.
+fn env_variables() -> HashMap<String, String> {
+    std::env::vars()
+        .filter_map(|(key, value)| {
+            Some((key.strip_prefix(ENV_VARIABLE_PREFIX)?.to_string(), value))
+        })
+        .collect()
+}
+
+const ENV_VARIABLE_PREFIX: &str = "CU_VAR_";
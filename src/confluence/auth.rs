@@ -0,0 +1,316 @@
+use crate::error::{Error, Result};
+use crate::secret::SecretSource;
+use clap::ValueEnum;
+use derive_more::Debug;
+use reqwest::RequestBuilder;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, info, instrument, warn, Level};
+
+const OAUTH_TOKEN_URL: &str = "https://auth.atlassian.com/oauth/token";
+pub const ATLASSIAN_API_URL: &str = "https://api.atlassian.com";
+
+// Access tokens are refreshed this long before they expire, to avoid using a token that expires in flight.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+// ###################################################### //
+//                      Flavor Enum                       //
+// ###################################################### //
+
+/// The kind of Confluence instance, which decides the API versions and authentication used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Flavor {
+    /// Confluence Cloud, hosted by Atlassian.
+    #[default]
+    Cloud,
+    /// Self-hosted Confluence Data Center.
+    Datacenter,
+}
+
+impl Flavor {
+    /// The context path Confluence is served from if not explicitly configured.
+    pub fn default_context_path(&self) -> &'static str {
+        match self {
+            Flavor::Cloud => "/wiki",
+            Flavor::Datacenter => "",
+        }
+    }
+
+    /// Confluence Cloud uses basic auth with an API token, Data Center uses a personal access token.
+    pub fn default_auth_method(&self) -> AuthMethod {
+        match self {
+            Flavor::Cloud => AuthMethod::Basic,
+            Flavor::Datacenter => AuthMethod::Bearer,
+        }
+    }
+}
+
+/// How requests to Confluence are authenticated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMethod {
+    /// Basic auth with the user and an API token or password.
+    Basic,
+    /// A bearer token, like a Data Center personal access token.
+    Bearer,
+    /// An OAuth 2.0 access token refreshed with a refresh token.
+    Oauth,
+}
+
+// ###################################################### //
+//                  ClientOptions Struct                  //
+// ###################################################### //
+
+/// Options to connect to a Confluence instance, see [`ConfluenceClient::connect`](super::ConfluenceClient::connect).
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// The URL of the Confluence instance, including the `https://` scheme.
+    pub fqdn: String,
+    /// The kind of Confluence instance.
+    pub flavor: Flavor,
+    /// The path Confluence is served from, defaults to [`Flavor::default_context_path`].
+    pub context_path: Option<String>,
+    /// Defaults to [`Flavor::default_auth_method`].
+    pub auth_method: Option<AuthMethod>,
+    /// The user for basic auth.
+    pub user: Option<String>,
+    /// The API token, password or bearer token, or the client secret for OAuth.
+    #[debug("\"<redacted>\"")]
+    pub secret: String,
+    /// The client id of the OAuth app.
+    pub oauth_client_id: Option<String>,
+    /// The OAuth refresh token, read from `oauth_refresh_token_file` if not set.
+    #[debug("\"<redacted>\"")]
+    pub oauth_refresh_token: Option<String>,
+    /// File the OAuth refresh token is read from and rotated tokens are written to.
+    pub oauth_refresh_token_file: Option<String>,
+    /// The cloud id of the site for OAuth, looked up from the URL if not set.
+    pub cloud_id: Option<String>,
+}
+
+// ###################################################### //
+//                       Auth Enum                        //
+// ###################################################### //
+
+#[derive(Debug)]
+pub enum Auth {
+    Basic {
+        user: String,
+        #[debug("\"<redacted>\"")]
+        secret: String,
+    },
+    Bearer {
+        #[debug("\"<redacted>\"")]
+        token: String,
+    },
+    OAuth(OAuth),
+}
+
+impl Auth {
+    /// Fails if values required by the authentication method are missing.
+    pub fn new(options: &ClientOptions) -> Result<Self> {
+        let method = options
+            .auth_method
+            .unwrap_or(options.flavor.default_auth_method());
+
+        match (method, options.flavor) {
+            (AuthMethod::Basic, _) => Ok(Auth::Basic {
+                user: options.user.clone().ok_or(Error::UserMissing)?,
+                secret: options.secret.to_string(),
+            }),
+            (AuthMethod::Bearer, _) => Ok(Auth::Bearer {
+                token: options.secret.to_string(),
+            }),
+            (AuthMethod::Oauth, Flavor::Datacenter) => Err(Error::OAuthNotSupported),
+            (AuthMethod::Oauth, Flavor::Cloud) => {
+                let client_id = options
+                    .oauth_client_id
+                    .as_deref()
+                    .ok_or(Error::OAuthClientIdMissing)?;
+                // The token file is read if no refresh token is given, rotated tokens are written back to it.
+                let refresh_token = match (
+                    &options.oauth_refresh_token,
+                    &options.oauth_refresh_token_file,
+                ) {
+                    (Some(token), _) => Some(token.to_string()),
+                    (None, Some(path)) => {
+                        Some(SecretSource::File(path.to_string()).resolve(&options.fqdn, None)?)
+                    }
+                    (None, None) => None,
+                };
+
+                Ok(Auth::OAuth(
+                    OAuth::new(client_id, &options.secret, refresh_token.as_deref())
+                        .with_refresh_token_file(options.oauth_refresh_token_file.as_deref()),
+                ))
+            }
+        }
+    }
+
+    pub async fn apply(
+        &self,
+        client: &reqwest::Client,
+        request: RequestBuilder,
+    ) -> Result<RequestBuilder> {
+        let request = match self {
+            Auth::Basic { user, secret } => request.basic_auth(user, Some(secret)),
+            Auth::Bearer { token } => request.bearer_auth(token),
+            Auth::OAuth(oauth) => request.bearer_auth(oauth.access_token(client).await?),
+        };
+
+        Ok(request)
+    }
+}
+
+// ###################################################### //
+//                      OAuth Struct                      //
+// ###################################################### //
+
+#[derive(Debug)]
+pub struct OAuth {
+    client_id: String,
+    #[debug("\"<redacted>\"")]
+    client_secret: String,
+    #[debug("\"<redacted>\"")]
+    refresh_token: Mutex<Option<String>>,
+    refresh_token_file: Option<String>,
+    #[debug("\"<redacted>\"")]
+    access_token: Mutex<Option<AccessToken>>,
+}
+
+struct AccessToken {
+    token: String,
+    expires_at: Instant,
+}
+
+#[derive(Serialize)]
+struct TokenRequest<'a> {
+    grant_type: &'a str,
+    client_id: &'a str,
+    client_secret: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+    refresh_token: Option<String>,
+}
+
+impl OAuth {
+    /// Uses the refresh token grant if a refresh token is given, otherwise the client credentials grant.
+    pub fn new(client_id: &str, client_secret: &str, refresh_token: Option<&str>) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            refresh_token: Mutex::new(refresh_token.map(|x| x.to_string())),
+            refresh_token_file: None,
+            access_token: Mutex::new(None),
+        }
+    }
+
+    /// Rotated refresh tokens are written to this file, so the next run can use them.
+    pub fn with_refresh_token_file(mut self, path: Option<&str>) -> Self {
+        self.refresh_token_file = path.map(|x| x.to_string());
+        self
+    }
+
+    // Returns the current access token, requesting a new one if it's missing or about to expire.
+    async fn access_token(&self, client: &reqwest::Client) -> Result<String> {
+        let mut access_token = self.access_token.lock().await;
+
+        if let Some(token) = access_token.as_ref() {
+            if Instant::now() + TOKEN_EXPIRY_MARGIN < token.expires_at {
+                return Ok(token.token.to_string());
+            }
+        }
+
+        let token = self.request_token(client).await?;
+        let value = token.token.to_string();
+        *access_token = Some(token);
+
+        Ok(value)
+    }
+
+    #[instrument(skip_all, err(Display, level = Level::DEBUG))]
+    async fn request_token(&self, client: &reqwest::Client) -> Result<AccessToken> {
+        let mut refresh_token = self.refresh_token.lock().await;
+
+        let grant_type = match refresh_token.is_some() {
+            true => "refresh_token",
+            false => "client_credentials",
+        };
+
+        debug!(grant_type, "requesting oauth access token");
+
+        let body = TokenRequest {
+            grant_type,
+            client_id: &self.client_id,
+            client_secret: &self.client_secret,
+            refresh_token: refresh_token.as_deref(),
+        };
+
+        let response = client
+            .post(OAUTH_TOKEN_URL)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TokenResponse>()
+            .await?;
+
+        // Atlassian uses rotating refresh tokens, so the new refresh token must be used for the next request.
+        // The previous refresh token is invalidated, so the new one is saved for the next run.
+        if let Some(new_refresh_token) = response.refresh_token {
+            if refresh_token.as_ref() != Some(&new_refresh_token) {
+                self.save_refresh_token(&new_refresh_token);
+            }
+            *refresh_token = Some(new_refresh_token);
+        }
+
+        Ok(AccessToken {
+            token: response.access_token,
+            expires_at: Instant::now() + Duration::from_secs(response.expires_in),
+        })
+    }
+
+    // The token itself is never logged. Without a token file the rotated token is lost at the end of the run.
+    fn save_refresh_token(&self, token: &str) {
+        let Some(path) = &self.refresh_token_file else {
+            warn!("the oauth refresh token was rotated and the previous one is no longer valid. Use --oauth-refresh-token-file to save rotated refresh tokens.");
+            return;
+        };
+
+        match std::fs::write(path, token) {
+            Ok(()) => info!(path, "saved rotated oauth refresh token"),
+            Err(error) => {
+                warn!(path, %error, "the oauth refresh token was rotated, but could not be saved. The previous refresh token is no longer valid.")
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TenantInfo {
+    cloud_id: String,
+}
+
+/// Looks up the cloud id of an Atlassian Cloud site, which is needed for requests through api.atlassian.com.
+#[instrument(skip(client), ret(level = Level::TRACE), err(Display, level = Level::DEBUG))]
+pub async fn get_cloud_id(client: &reqwest::Client, fqdn: &str) -> Result<String> {
+    let tenant_info = client
+        .get(format!("{}/_edge/tenant_info", fqdn.trim_end_matches('/')))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<TenantInfo>()
+        .await?;
+
+    Ok(tenant_info.cloud_id)
+}
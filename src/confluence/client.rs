@@ -1,23 +1,49 @@
-use super::restriction::Restriction;
+use super::auth::{self, Auth, ClientOptions, Flavor, ATLASSIAN_API_URL};
+use super::restriction::{ResolvedPrincipals, Restriction};
 use super::ConfluencePage;
-use crate::config::Page;
+use crate::cache::CachedLink;
+use crate::config::{Page, Principals, RestrictionsConfig};
 use crate::error::{Error, Result};
 use reqwest::{
     header::{HeaderMap, HeaderValue},
     multipart::Form,
     ClientBuilder, Response,
 };
-use serde::Deserialize;
-use tracing::{debug, error, info, instrument, Level};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use tokio::sync::OnceCell;
+use tracing::{debug, error, info, instrument, warn, Level};
 
+/// Response of the Confluence APIv2 pages endpoint, used by Confluence Cloud.
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct PageResponse {
     pub version: Version,
+    pub parent_id: Option<String>,
     pub labels: Option<Labels>,
     #[serde(rename = "_links")]
     pub links: Links,
 }
 
+/// Response of the Confluence APIv1 content endpoint, used by Confluence Data Center.
+#[derive(Deserialize, Debug)]
+pub struct ContentResponse {
+    pub version: Version,
+    #[serde(default)]
+    pub ancestors: Vec<Ancestor>,
+    pub metadata: Option<ContentMetadata>,
+    #[serde(rename = "_links")]
+    pub links: Links,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ContentMetadata {
+    pub labels: Option<Labels>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Links {
     base: String,
@@ -29,64 +55,365 @@ pub struct Version {
     pub number: u64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Labels {
     pub results: Vec<LabelResult>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct LabelResult {
     pub name: String,
 }
 
+/// Response of the Confluence APIv1 content endpoint when searching for pages.
+#[derive(Deserialize, Debug)]
+pub struct ContentSearchResponse<T> {
+    pub results: Vec<T>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ContentResult {
+    pub id: String,
+    #[serde(default)]
+    pub ancestors: Vec<Ancestor>,
+}
+
 #[derive(Deserialize, Debug)]
+pub struct Ancestor {
+    pub id: String,
+}
+
+/// A content property of a page, stored as JSON.
+#[derive(Deserialize, Debug)]
+pub struct ContentProperty<T> {
+    pub value: T,
+    pub version: Version,
+}
+
+/// Content property storing the labels set from the config, so they can be removed once they're no longer configured.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ManagedLabels {
+    pub labels: Vec<String>,
+}
+
+/// Identifies the config or repository a page is published from.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct PageOwner {
+    pub owner: String,
+}
+
+const LABELS_PROPERTY_KEY: &str = "confluence-updater-labels";
+const OWNER_PROPERTY_KEY: &str = "confluence-updater-owner";
+
+// Labels with these prefixes are always set by the tool.
+const MANAGED_LABEL_PREFIXES: [&str; 2] = ["page-sha/", "pa-token/"];
+
+/// A page found by a CQL search.
+#[derive(Deserialize, Debug)]
+pub struct SearchResult {
+    pub id: String,
+    pub title: String,
+    pub version: Version,
+    pub metadata: Option<ContentMetadata>,
+}
+
+impl SearchResult {
+    pub fn labels(&self) -> Vec<String> {
+        label_names(self.metadata.as_ref().and_then(|x| x.labels.clone()))
+    }
+}
+
+/// Confluence Cloud identifies users by account id and email, Data Center by username and user key.
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct User {
-    pub account_id: String,
-    pub email: String,
+    pub account_id: Option<String>,
+    pub email: Option<String>,
+    pub username: Option<String>,
 }
 
+impl User {
+    /// The part of the `pa-token/` label identifying the owner of the token.
+    /// OAuth service accounts may not expose an email, in which case the account id is used.
+    pub fn token_label(&self) -> Result<String> {
+        let name = match (&self.email, &self.username, &self.account_id) {
+            (Some(email), _, _) => email.split_once("@").map(|x| x.0),
+            (None, Some(username), _) => Some(username.as_str()),
+            (None, None, Some(account_id)) => Some(account_id.as_str()),
+            (None, None, None) => None,
+        };
+
+        name.map(|x| x.replace(['.', ':'], "-").to_lowercase())
+            .ok_or(Error::CurrentUserEmailMissing)
+    }
+}
+
+/// The parts of a page used by the client, independent of the API version it was fetched with.
+#[derive(Debug, Clone)]
+pub struct PageInfo {
+    pub version: u64,
+    pub parent_id: Option<String>,
+    pub labels: Vec<String>,
+    pub link: String,
+}
+
+impl From<PageResponse> for PageInfo {
+    fn from(response: PageResponse) -> Self {
+        Self {
+            version: response.version.number,
+            parent_id: response.parent_id,
+            labels: label_names(response.labels),
+            link: format!("{}{}", response.links.base, response.links.webui),
+        }
+    }
+}
+
+impl From<ContentResponse> for PageInfo {
+    fn from(mut response: ContentResponse) -> Self {
+        Self {
+            version: response.version.number,
+            parent_id: response.ancestors.pop().map(|x| x.id),
+            labels: label_names(response.metadata.and_then(|x| x.labels)),
+            link: format!("{}{}", response.links.base, response.links.webui),
+        }
+    }
+}
+
+/// The outcome of [`ConfluenceClient::update_confluence_page`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    /// The page was created or updated to the given version.
+    Updated {
+        /// The version number of the page after the update.
+        version: u64,
+    },
+    /// The page is unchanged, so it wasn't updated.
+    Skipped,
+}
+
+// Quotes and backslashes must be escaped in CQL strings.
+fn escape_cql(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn is_loopback(url: &str) -> bool {
+    let Some(host) = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|x| x.host_str().map(String::from))
+    else {
+        return false;
+    };
+
+    match host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        Ok(ip) => ip.is_loopback(),
+        Err(_) => host == "localhost",
+    }
+}
+
+fn label_names(labels: Option<Labels>) -> Vec<String> {
+    match labels {
+        Some(labels) => labels.results.into_iter().map(|x| x.name).collect(),
+        None => vec![],
+    }
+}
+
+/// Client for the Confluence REST API of a single instance, caching pages and links it looked up.
 #[derive(Debug)]
 pub struct ConfluenceClient {
     client: reqwest::Client,
     base_url: String,
-    user: String,
-    secret: String,
+    flavor: Flavor,
+    context_path: String,
+    auth: Auth,
+    cache: ClientCache,
+}
+
+// Responses cached for the duration of a run, so each page and the current user is only fetched once.
+// Page links are persisted between runs. Links contain the title, so they're dropped when the tool changes the page.
+#[derive(Debug, Default)]
+struct ClientCache {
+    current_user: OnceCell<User>,
+    pages: Mutex<HashMap<String, PageInfo>>,
+    links: Mutex<HashMap<String, CachedLink>>,
 }
 
 impl ConfluenceClient {
+    /// Creates a client from the connection options. OAuth requests are sent through the Atlassian API gateway.
+    #[instrument(skip_all, name = "confluence_client::connect" err(Debug, level = Level::DEBUG))]
+    pub async fn connect(options: &ClientOptions) -> Result<Self> {
+        let auth = Auth::new(options)?;
+
+        let base_url = match &auth {
+            Auth::OAuth(_) => {
+                let cloud_id = match &options.cloud_id {
+                    Some(cloud_id) => cloud_id.to_string(),
+                    None => auth::get_cloud_id(&reqwest::Client::new(), &options.fqdn).await?,
+                };
+                format!("{ATLASSIAN_API_URL}/ex/confluence/{cloud_id}")
+            }
+            _ => options.fqdn.to_string(),
+        };
+
+        Self::new(
+            &base_url,
+            options.flavor,
+            options.context_path.as_deref(),
+            auth,
+        )
+    }
+
+    /// Creates a client for the base URL. Confluence Cloud requires HTTPS, Data Center also accepts HTTP.
     #[instrument(skip_all, name = "confluence_client::new" err(Debug, level = Level::DEBUG))]
-    pub fn new(fqdn: &str, user: &str, secret: &str) -> Result<Self> {
+    pub(crate) fn new(
+        fqdn: &str,
+        flavor: Flavor,
+        context_path: Option<&str>,
+        auth: Auth,
+    ) -> Result<Self> {
         let client = ClientBuilder::new().build()?;
-        let base_url = fqdn.to_string();
+        let base_url = fqdn.trim_end_matches('/').to_string();
 
-        if !base_url.starts_with("https://") {
-            let error = Error::HttpsProtocolSchemeMissing(base_url);
-            error!(%error);
-            return Err(error);
+        match flavor {
+            // Plain HTTP is allowed for loopback hosts, e.g. a mock server or an SSH tunnel.
+            Flavor::Cloud if base_url.starts_with("http://") && is_loopback(&base_url) => {
+                warn!(%base_url, "using unencrypted HTTP for a loopback host");
+            }
+            Flavor::Cloud if !base_url.starts_with("https://") => {
+                let error = Error::HttpsProtocolSchemeMissing(base_url);
+                error!(%error);
+                return Err(error);
+            }
+            Flavor::Datacenter
+                if !base_url.starts_with("https://") && !base_url.starts_with("http://") =>
+            {
+                let error = Error::ProtocolSchemeMissing(base_url);
+                error!(%error);
+                return Err(error);
+            }
+            Flavor::Datacenter if base_url.starts_with("http://") => {
+                warn!(%base_url, "using unencrypted HTTP for Confluence Data Center");
+            }
+            _ => (),
         }
 
-        debug!(%base_url, "creating confluence client");
+        // Normalize the context path to either be empty or start with a slash and have no trailing slash.
+        let context_path = context_path
+            .unwrap_or(flavor.default_context_path())
+            .trim_matches('/');
+        let context_path = match context_path.is_empty() {
+            true => String::new(),
+            false => format!("/{context_path}"),
+        };
+
+        debug!(%base_url, ?flavor, %context_path, "creating confluence client");
 
         Ok(Self {
             client,
             base_url,
-            user: user.to_string(),
-            secret: secret.to_string(),
+            flavor,
+            context_path,
+            auth,
+            cache: ClientCache::default(),
         })
     }
 
-    #[instrument(skip(self), ret(level = Level::TRACE), fields(base_url = self.base_url, path = path, method = method.to_string()))]
-    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+    /// Identifies the Confluence instance in the cache file.
+    pub fn cache_key(&self) -> String {
+        format!("{}{}", self.base_url, self.context_path)
+    }
+
+    /// The page links looked up so far, to be persisted in the cache file.
+    pub fn cached_links(&self) -> HashMap<String, CachedLink> {
+        self.cache
+            .links
+            .lock()
+            .expect("poisoned cache lock")
+            .clone()
+    }
+
+    /// Adds page links restored from the cache file.
+    pub fn extend_cached_links(&self, links: HashMap<String, CachedLink>) {
+        self.cache
+            .links
+            .lock()
+            .expect("poisoned cache lock")
+            .extend(links);
+    }
+
+    // Cached pages and links must be invalidated when a page is changed, since a new title or parent changes the link.
+    fn invalidate_page(&self, page_id: &str) {
+        self.cache
+            .pages
+            .lock()
+            .expect("poisoned cache lock")
+            .remove(page_id);
+
+        self.cache
+            .links
+            .lock()
+            .expect("poisoned cache lock")
+            .remove(page_id);
+    }
+
+    // The request is not logged on return, since it contains the credentials.
+    #[instrument(skip(self), fields(base_url = self.base_url, path = path, method = method.to_string()))]
+    async fn request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+    ) -> Result<reqwest::RequestBuilder> {
         debug!("created request");
-        self.client
-            .request(method, format!("{}{}", self.base_url, path))
-            .basic_auth(self.user.to_string(), Some(self.secret.to_string()))
+        let request = self.client.request(
+            method,
+            format!("{}{}{}", self.base_url, self.context_path, path),
+        );
+        self.auth.apply(&self.client, request).await
     }
 
     #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
     async fn get(&self, path: &str) -> Result<Response> {
         self.request(reqwest::Method::GET, path)
+            .await?
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(Error::from)
+    }
+
+    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    async fn get_with_query<T: serde::Serialize + ?Sized>(
+        &self,
+        path: &str,
+        query: &T,
+    ) -> Result<Response> {
+        self.request(reqwest::Method::GET, path)
+            .await?
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(Error::from)
+    }
+
+    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    async fn post<T: serde::Serialize>(&self, path: &str, body: &T) -> Result<Response> {
+        self.request(reqwest::Method::POST, path)
+            .await?
+            .json(body)
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(Error::from)
+    }
+
+    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    async fn delete(&self, path: &str) -> Result<Response> {
+        self.request(reqwest::Method::DELETE, path)
+            .await?
             .send()
             .await?
             .error_for_status()
@@ -96,6 +423,7 @@ impl ConfluenceClient {
     #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
     async fn put<T: serde::Serialize>(&self, path: &str, body: &T) -> Result<Response> {
         self.request(reqwest::Method::PUT, path)
+            .await?
             .json(body)
             .send()
             .await?
@@ -110,7 +438,10 @@ impl ConfluenceClient {
         header_map: Option<HeaderMap<HeaderValue>>,
         form: Form,
     ) -> Result<Response> {
-        let mut req = self.request(reqwest::Method::PUT, path).multipart(form);
+        let mut req = self
+            .request(reqwest::Method::PUT, path)
+            .await?
+            .multipart(form);
 
         if let Some(headers) = header_map {
             req = req.headers(headers);
@@ -118,45 +449,361 @@ impl ConfluenceClient {
 
         req.send().await?.error_for_status().map_err(Error::from)
     }
+    /// Returns the page, which is cached until the page is changed by the client.
     #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
-    async fn get_page_version(&self, page_id: &str) -> Result<u64> {
-        let response = self
-            .get(format!("/wiki/api/v2/pages/{page_id}").as_ref())
-            .await?
-            .json::<PageResponse>()
-            .await?;
-        let version = response.version.number;
+    pub(crate) async fn get_page(&self, page_id: &str) -> Result<PageInfo> {
+        if let Some(page) = self
+            .cache
+            .pages
+            .lock()
+            .expect("poisoned cache lock")
+            .get(page_id)
+        {
+            debug!(page_id, "using cached page");
+            return Ok(page.clone());
+        }
+
+        let page = self.fetch_page(page_id).await?;
+
+        self.cache
+            .pages
+            .lock()
+            .expect("poisoned cache lock")
+            .insert(page_id.to_string(), page.clone());
+
+        Ok(page)
+    }
+
+    // Confluence Cloud uses APIv2 for reading pages, Data Center only supports APIv1.
+    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    async fn fetch_page(&self, page_id: &str) -> Result<PageInfo> {
+        let page = match self.flavor {
+            Flavor::Cloud => self
+                .get(&format!("/api/v2/pages/{page_id}?include-labels=true"))
+                .await?
+                .json::<PageResponse>()
+                .await?
+                .into(),
+            Flavor::Datacenter => self
+                .get(&format!(
+                    "/rest/api/content/{page_id}?expand=version,metadata.labels,ancestors"
+                ))
+                .await?
+                .json::<ContentResponse>()
+                .await?
+                .into(),
+        };
+
+        Ok(page)
+    }
 
+    /// The current version number of the page.
+    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    pub async fn get_page_version(&self, page_id: &str) -> Result<u64> {
+        let version = self.get_page(page_id).await?.version;
         Ok(version)
     }
 
+    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    async fn get_page_parent_id(&self, page_id: &str) -> Result<Option<String>> {
+        let parent_id = self.get_page(page_id).await?.parent_id;
+        Ok(parent_id)
+    }
+
+    /// Returns the WebUI url of the page. Links are cached for the lifetime of the client.
     #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
     pub async fn get_page_link(&self, page_id: &str) -> Result<String> {
+        if let Some(link) = self
+            .cache
+            .links
+            .lock()
+            .expect("poisoned cache lock")
+            .get(page_id)
+        {
+            debug!(page_id, "using cached page link");
+            return Ok(link.link.to_string());
+        }
+
+        let link = self.get_page(page_id).await?.link;
+
+        self.cache
+            .links
+            .lock()
+            .expect("poisoned cache lock")
+            .insert(page_id.to_string(), CachedLink::new(&link));
+
+        Ok(link)
+    }
+
+    /// Returns all pages with the exact title in the space. APIv1 is supported by both Cloud and Data Center.
+    #[instrument(skip(self), ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    pub(crate) async fn find_pages(
+        &self,
+        space_key: &str,
+        title: &str,
+    ) -> Result<Vec<ContentResult>> {
+        let query = [
+            ("spaceKey", space_key),
+            ("title", title),
+            ("type", "page"),
+            ("expand", "ancestors"),
+        ];
+
         let response = self
-            .get(format!("/wiki/api/v2/pages/{page_id}").as_ref())
+            .get_with_query("/rest/api/content", &query)
             .await?
-            .json::<PageResponse>()
+            .json::<ContentSearchResponse<ContentResult>>()
             .await?;
-        let base = response.links.base;
-        let webui = response.links.webui;
 
-        Ok(format!("{base}{webui}"))
+        Ok(response.results)
     }
 
-    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
-    pub async fn get_current_user(&self) -> Result<User> {
-        let response = self
-            .get("/wiki/rest/api/user/current")
+    /// Returns the id of the parent page with the title in the space. Fails if no unique page is found.
+    #[instrument(skip(self), ret(level = Level::TRACE), err(Display))]
+    pub async fn find_parent_id(&self, space_key: &str, parent: &str) -> Result<String> {
+        self.find_unique_page(space_key, parent, None)
+            .await?
+            .ok_or_else(|| Error::PageNotFound(format!("{parent} in space {space_key}")))
+    }
+
+    /// Returns the id of the page with the title in the space, below the parent page if set.
+    /// Fails if more than one page matches.
+    #[instrument(skip(self), ret(level = Level::TRACE), err(Display))]
+    pub async fn find_unique_page(
+        &self,
+        space_key: &str,
+        title: &str,
+        parent_id: Option<&str>,
+    ) -> Result<Option<String>> {
+        let is_child = |page: &ContentResult| match parent_id {
+            Some(parent_id) => page.ancestors.last().is_some_and(|x| x.id == parent_id),
+            None => true,
+        };
+
+        let mut pages: Vec<ContentResult> = self
+            .find_pages(space_key, title)
+            .await?
+            .into_iter()
+            .filter(is_child)
+            .collect();
+
+        match (pages.pop(), pages.is_empty()) {
+            (Some(page), true) => {
+                debug!(page_id = page.id, title, "found page by title");
+                Ok(Some(page.id))
+            }
+            (Some(_), false) => Err(Error::AmbiguousPage(format!(
+                "{title} in space {space_key}"
+            ))),
+            (None, _) => Ok(None),
+        }
+    }
+
+    /// Creates an empty page, which is filled by the following update. Returns the id of the page.
+    #[instrument(skip(self), ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    pub async fn create_page(
+        &self,
+        space_key: &str,
+        title: &str,
+        parent_id: Option<&str>,
+    ) -> Result<String> {
+        let ancestors: Vec<serde_json::Value> = parent_id
+            .map(|id| serde_json::json!({ "id": id }))
+            .into_iter()
+            .collect();
+
+        let body = serde_json::json!({
+            "type": "page",
+            "title": title,
+            "space": { "key": space_key },
+            "ancestors": ancestors,
+            "body": { "storage": { "value": "", "representation": "storage" } },
+        });
+
+        let page = self
+            .post("/rest/api/content", &body)
+            .await?
+            .json::<ContentResult>()
+            .await?;
+
+        info!(page_id = page.id, "created page.");
+        Ok(page.id)
+    }
+
+    /// Returns all pages matching the CQL query, following the pagination of the search results.
+    #[instrument(skip(self), ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    pub(crate) async fn search_pages(&self, cql: &str) -> Result<Vec<SearchResult>> {
+        let limit = 100;
+        let mut pages = vec![];
+
+        loop {
+            let start = pages.len().to_string();
+            let query = [
+                ("cql", cql),
+                ("expand", "version,metadata.labels"),
+                ("start", &start),
+                ("limit", &limit.to_string()),
+            ];
+
+            let results = self
+                .get_with_query("/rest/api/content/search", &query)
+                .await?
+                .json::<ContentSearchResponse<SearchResult>>()
+                .await?
+                .results;
+
+            let is_last_page = results.len() < limit;
+            pages.extend(results);
+
+            if is_last_page {
+                return Ok(pages);
+            }
+        }
+    }
+
+    /// Returns the pages published by the owner with the credentials of the current user. The pages are found by the
+    /// `pa-token/` label and only kept if their owner property matches, so pages of other configs are never returned.
+    #[instrument(skip(self), ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    pub(crate) async fn find_managed_pages(
+        &self,
+        space_key: &str,
+        parent_id: Option<&str>,
+        owner: &str,
+    ) -> Result<Vec<SearchResult>> {
+        let user_label = self.get_current_user().await?.token_label()?;
+
+        let mut cql = format!(
+            "type = page and space = \"{}\" and label = \"pa-token/{}\"",
+            escape_cql(space_key),
+            escape_cql(&user_label)
+        );
+
+        if let Some(parent_id) = parent_id {
+            cql.push_str(&format!(" and ancestor = \"{}\"", escape_cql(parent_id)));
+        }
+
+        let mut managed_pages = vec![];
+
+        for page in self.search_pages(&cql).await? {
+            let page_owner = self
+                .get_property::<PageOwner>(&page.id, OWNER_PROPERTY_KEY)
+                .await?;
+
+            match page_owner {
+                Some(page_owner) if page_owner.value.owner == owner => managed_pages.push(page),
+                _ => debug!(page_id = page.id, "page has another owner, skipping."),
+            }
+        }
+
+        Ok(managed_pages)
+    }
+
+    /// Archiving is only supported by Confluence Cloud.
+    #[instrument(skip(self), err(Debug, level = Level::DEBUG))]
+    pub async fn archive_page(&self, page_id: &str) -> Result<()> {
+        if self.flavor != Flavor::Cloud {
+            return Err(Error::ArchiveNotSupported);
+        }
+
+        let body = serde_json::json!({ "pages": [{ "id": page_id }] });
+        self.post("/rest/api/content/archive", &body).await?;
+        self.invalidate_page(page_id);
+
+        Ok(())
+    }
+
+    /// Moves the page to the trash of the space.
+    #[instrument(skip(self), err(Debug, level = Level::DEBUG))]
+    pub async fn trash_page(&self, page_id: &str) -> Result<()> {
+        self.delete(&format!("/rest/api/content/{page_id}")).await?;
+        self.invalidate_page(page_id);
+        Ok(())
+    }
+
+    /// Adds global labels to the page, keeping its existing labels.
+    #[instrument(skip(self), err(Debug, level = Level::DEBUG))]
+    pub async fn add_labels(&self, page_id: &str, labels: &[String]) -> Result<()> {
+        let body: Vec<serde_json::Value> = labels
+            .iter()
+            .map(|name| serde_json::json!({ "prefix": "global", "name": name }))
+            .collect();
+
+        self.post(&format!("/rest/api/content/{page_id}/label"), &body)
+            .await?;
+
+        self.invalidate_page(page_id);
+        Ok(())
+    }
+
+    /// Removes the label from the page.
+    #[instrument(skip(self), err(Debug, level = Level::DEBUG))]
+    pub async fn remove_label(&self, page_id: &str, label: &str) -> Result<()> {
+        let path = format!("/rest/api/content/{page_id}/label");
+
+        self.request(reqwest::Method::DELETE, &path)
             .await?
-            .json::<User>()
+            .query(&[("name", label)])
+            .send()
+            .await?
+            .error_for_status()?;
+
+        self.invalidate_page(page_id);
+        Ok(())
+    }
+
+    /// Replaces the content of the page with the notice and removes the labels marking it as managed by the tool.
+    #[instrument(skip(self, notice_html), err(Debug, level = Level::DEBUG))]
+    pub(crate) async fn replace_with_notice(
+        &self,
+        page: &SearchResult,
+        notice_html: &str,
+    ) -> Result<()> {
+        let body = serde_json::json!({
+            "type": "page",
+            "title": page.title,
+            "status": "current",
+            "version": { "number": page.version.number + 1, "message": "Removed from source" },
+            "body": { "storage": { "value": notice_html, "representation": "storage" } },
+        });
+
+        self.put(&format!("/rest/api/content/{}", page.id), &body)
             .await?;
+        self.invalidate_page(&page.id);
+
+        let managed_labels = page.labels().into_iter().filter(|x| {
+            MANAGED_LABEL_PREFIXES
+                .iter()
+                .any(|prefix| x.starts_with(prefix))
+        });
+
+        for label in managed_labels {
+            self.remove_label(&page.id, &label).await?;
+        }
 
-        Ok(response)
+        Ok(())
     }
 
+    /// Returns the user of the credentials, which is only fetched once.
+    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    pub(crate) async fn get_current_user(&self) -> Result<User> {
+        let fetch_user = || async {
+            let response = self
+                .get("/rest/api/user/current")
+                .await?
+                .json::<User>()
+                .await?;
+
+            Ok::<User, Error>(response)
+        };
+
+        let user = self.cache.current_user.get_or_try_init(fetch_user).await?;
+        Ok(user.clone())
+    }
+
+    /// Uploads the file as an attachment of the page, replacing an attachment with the same file name.
     #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
     pub async fn upload_attachment(&self, page_id: &str, file_path: &str) -> Result<()> {
-        let path = format!("/wiki/rest/api/content/{page_id}/child/attachment");
+        let path = format!("/rest/api/content/{page_id}/child/attachment");
 
         let mut header_map = HeaderMap::new();
         header_map.insert("X-Atlassian-Token", HeaderValue::from_static("nocheck"));
@@ -173,18 +820,14 @@ impl ConfluenceClient {
 
     #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
     async fn get_page_sha(&self, page_id: &str) -> Result<Option<String>> {
-        let path = format!("/wiki/api/v2/pages/{page_id}?include-labels=true");
-        let response = self.get(&path).await?.json::<PageResponse>().await?;
-
-        let sha_label: String = match response.labels {
-            Some(labels) => labels
-                .results
-                .iter()
-                .filter(|x| x.name.starts_with("page-sha/"))
-                .map(|x| x.name.split("page-sha/").collect::<String>())
-                .collect(),
-            None => String::new(),
-        };
+        let sha_label: String = self
+            .get_page(page_id)
+            .await?
+            .labels
+            .iter()
+            .filter(|x| x.starts_with("page-sha/"))
+            .map(|x| x.split("page-sha/").collect::<String>())
+            .collect();
 
         match sha_label.is_empty() {
             true => Ok(None),
@@ -192,44 +835,237 @@ impl ConfluenceClient {
         }
     }
 
-    async fn remove_page_restriction(&self, page_id: &str) -> Result<()> {
+    #[instrument(skip(self), err(Debug, level = Level::DEBUG))]
+    async fn get_property<T: DeserializeOwned>(
+        &self,
+        page_id: &str,
+        key: &str,
+    ) -> Result<Option<ContentProperty<T>>> {
+        let path = format!("/rest/api/content/{page_id}/property/{key}");
+        let response = self
+            .request(reqwest::Method::GET, &path)
+            .await?
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let property = response
+            .error_for_status()?
+            .json::<ContentProperty<T>>()
+            .await?;
+        Ok(Some(property))
+    }
+
+    // Creates the property, or updates it if a previous version exists.
+    #[instrument(skip(self, value), err(Debug, level = Level::DEBUG))]
+    async fn set_property<T: Serialize>(
+        &self,
+        page_id: &str,
+        key: &str,
+        value: T,
+        previous_version: Option<u64>,
+    ) -> Result<()> {
+        match previous_version {
+            Some(number) => {
+                let path = format!("/rest/api/content/{page_id}/property/{key}");
+                let body = serde_json::json!({
+                    "key": key,
+                    "value": value,
+                    "version": { "number": number + 1 },
+                });
+                self.put(&path, &body).await?;
+            }
+            None => {
+                let path = format!("/rest/api/content/{page_id}/property");
+                let body = serde_json::json!({ "key": key, "value": value });
+                self.post(&path, &body).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Marks the page as published by the owner, so pruning can tell its own pages apart from those of other configs.
+    #[instrument(skip(self), err(Debug, level = Level::DEBUG))]
+    async fn set_page_owner(&self, page_id: &str, owner: &str) -> Result<()> {
+        let previous = self
+            .get_property::<PageOwner>(page_id, OWNER_PROPERTY_KEY)
+            .await?;
+
+        if previous.as_ref().is_some_and(|x| x.value.owner == owner) {
+            return Ok(());
+        }
+
+        let value = PageOwner {
+            owner: owner.to_string(),
+        };
+        let previous_version = previous.map(|x| x.version.number);
+        self.set_property(page_id, OWNER_PROPERTY_KEY, value, previous_version)
+            .await
+    }
+
+    // Removes the labels previously set by the tool that are no longer configured. Labels added by others are kept.
+    #[instrument(skip(self, current_labels, desired_labels), err(Debug, level = Level::DEBUG))]
+    async fn remove_stale_labels(
+        &self,
+        page_id: &str,
+        current_labels: &[String],
+        desired_labels: &[String],
+        config_labels: Vec<String>,
+    ) -> Result<()> {
+        let previous = self
+            .get_property::<ManagedLabels>(page_id, LABELS_PROPERTY_KEY)
+            .await?;
+        let previous_labels = previous.as_ref().map(|x| x.value.labels.as_slice());
+
+        let is_managed = |label: &String| {
+            MANAGED_LABEL_PREFIXES.iter().any(|x| label.starts_with(x))
+                || previous_labels.is_some_and(|x| x.contains(label))
+        };
+
+        let stale_labels = current_labels
+            .iter()
+            .filter(|x| is_managed(x) && !desired_labels.contains(x));
+
+        for label in stale_labels {
+            self.remove_label(page_id, label).await?;
+            info!("removed stale label [{label}].");
+        }
+
+        let is_unchanged = previous_labels.is_some_and(|x| x == config_labels.as_slice());
+
+        if !is_unchanged {
+            let value = ManagedLabels {
+                labels: config_labels,
+            };
+            let previous_version = previous.map(|x| x.version.number);
+            self.set_property(page_id, LABELS_PROPERTY_KEY, value, previous_version)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes all view and edit restrictions of the page.
+    pub async fn remove_page_restriction(&self, page_id: &str) -> Result<()> {
         let body = Restriction::no_restrictions();
-        let path = format!("/wiki/rest/api/content/{page_id}/restriction");
+        let path = format!("/rest/api/content/{page_id}/restriction");
         self.put(&path, &body).await?;
         Ok(())
     }
 
-    async fn set_page_read_only(&self, page_id: &str, account_id: &str) -> Result<()> {
-        let body = Restriction::read_only(account_id);
-        let path = format!("/wiki/rest/api/content/{page_id}/restriction");
+    /// Restricts viewing and editing of the page to the users and groups. The current user can always edit the page.
+    pub async fn set_page_restrictions(
+        &self,
+        page_id: &str,
+        restrictions: &RestrictionsConfig,
+    ) -> Result<()> {
+        let current_user = self.get_current_user().await?;
+
+        let view = match &restrictions.view {
+            Some(principals) => Some(self.resolve_principals(principals).await?),
+            None => None,
+        };
+
+        let edit = match &restrictions.edit {
+            Some(principals) => Some(self.resolve_principals(principals).await?),
+            None => None,
+        };
+
+        let body = Restriction::with_principals(&current_user, view, edit);
+        let path = format!("/rest/api/content/{page_id}/restriction");
+        self.put(&path, &body).await?;
+        Ok(())
+    }
+
+    async fn resolve_principals(&self, principals: &Principals) -> Result<ResolvedPrincipals> {
+        let mut users = vec![];
+
+        for user in &principals.users {
+            users.push(self.resolve_user(user).await?);
+        }
+
+        Ok(ResolvedPrincipals {
+            users,
+            groups: principals.groups.clone(),
+        })
+    }
+
+    // Confluence Cloud users are given by account id, Data Center users by username.
+    #[instrument(skip(self), ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    async fn resolve_user(&self, user: &str) -> Result<User> {
+        let known_user = |account_id: Option<&str>, username: Option<&str>| User {
+            account_id: account_id.map(|x| x.to_string()),
+            email: None,
+            username: username.map(|x| x.to_string()),
+        };
+
+        if self.flavor == Flavor::Datacenter {
+            return Ok(known_user(None, Some(user)));
+        }
+
+        // Emails can't be looked up, since Confluence Cloud doesn't search users by email.
+        match user.contains('@') {
+            true => Err(Error::EmailPrincipal(user.to_string())),
+            false => Ok(known_user(Some(user), None)),
+        }
+    }
+
+    /// Restricts editing of the page to the user.
+    pub(crate) async fn set_page_read_only(&self, page_id: &str, user: &User) -> Result<()> {
+        let body = Restriction::read_only(user);
+        let path = format!("/rest/api/content/{page_id}/restriction");
         self.put(&path, &body).await?;
         Ok(())
     }
 
+    /// Updates the page with the rendered content, labels, attachments and restrictions of the page.
+    /// A missing page is created first and its id set on the page.
+    /// Pages with an unchanged SHA are skipped.
     #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
-    pub async fn update_confluence_page(&self, page: &Page) -> Result<Option<reqwest::Response>> {
+    pub async fn update_confluence_page(&self, page: &mut Page) -> Result<UpdateOutcome> {
+        // Missing pages are only created here, so loading or validating the config doesn't change anything.
+        if let Some(new_page) = page.new_page.take() {
+            page.page_id = self
+                .create_page(
+                    &new_page.space_key,
+                    &page.title,
+                    new_page.parent_id.as_deref(),
+                )
+                .await?;
+        }
+
         let version = self.get_page_version(&page.page_id).await? + 1;
 
+        // A page with the wrong parent is updated even if the content is unchanged.
+        let current_parent_id = match &page.parent_id {
+            Some(_) => self.get_page_parent_id(&page.page_id).await?,
+            None => None,
+        };
+        let is_moved = page.parent_id.is_some() && current_parent_id != page.parent_id;
+
+        let user_label = self.get_current_user().await?.token_label()?;
+        let token_label = format!("pa-token/{user_label}");
+        let current_labels = self.get_page(&page.page_id).await?.labels;
+
+        // The token owner is part of the page state, so a page published by another account is updated and the
+        // stale `pa-token/` label removed, even if the content is unchanged.
+        let is_other_owner = current_labels
+            .iter()
+            .filter(|x| x.starts_with("pa-token/"))
+            .ne([&token_label]);
+
         if let Some(sha) = self.get_page_sha(&page.page_id).await? {
-            if sha == page.page_sha {
+            if sha == page.page_sha && !is_moved && !is_other_owner {
                 info!("no changes to page, skipping.");
-                return Ok(None);
+                return Ok(UpdateOutcome::Skipped);
             }
         }
 
-        let user_label = self
-            .get_current_user()
-            .await?
-            .email
-            .split_once("@")
-            .ok_or(Error::CurrentUserEmailMissing)?
-            .0
-            .replace(".", "-");
-
-        let labels = vec![
-            format!("page-sha/{}", page.page_sha),
-            format!("pa-token/{}", user_label),
-        ];
+        let labels = vec![format!("page-sha/{}", page.page_sha), token_label];
 
         for image_path in &page.html.image_paths {
             info!("uploading attachment [{}]", &image_path);
@@ -238,23 +1074,49 @@ impl ConfluenceClient {
                 .inspect_err(|error| error!(image=image_path, %error))?;
         }
 
-        let confluence_page = ConfluencePage::new(page, version).add_labels(labels);
+        let confluence_page = ConfluencePage::new(page, version);
+        let config_labels = confluence_page.label_names();
+        let confluence_page = confluence_page.add_labels(labels);
+        let desired_labels = confluence_page.label_names();
 
         // Below URL is for Confluence APIv1 because v2 does not support updating labels yet.
-        let path = format!("/wiki/rest/api/content/{}", &page.page_id);
-        let response = self.put(&path, &confluence_page).await?;
+        let path = format!("/rest/api/content/{}", &page.page_id);
+        self.put(&path, &confluence_page).await?;
+        self.invalidate_page(&page.page_id);
 
         info!("successfully updated page.");
 
-        if page.read_only == Some(true) {
-            let account_id = self.get_current_user().await?.account_id;
-            self.set_page_read_only(&page.page_id, &account_id).await?;
+        self.remove_stale_labels(
+            &page.page_id,
+            &current_labels,
+            &desired_labels,
+            config_labels,
+        )
+        .await?;
+
+        if let Some(owner) = &page.owner {
+            self.set_page_owner(&page.page_id, owner).await?;
+        }
+
+        if is_moved {
+            let from = current_parent_id.as_deref().unwrap_or("none");
+            let to = page.parent_id.as_deref().unwrap_or_default();
+            info!("moved page from parent [{from}] to [{to}].");
+        }
+
+        if let Some(restrictions) = &page.restrictions {
+            self.set_page_restrictions(&page.page_id, restrictions)
+                .await?;
+            debug!("set view and edit restrictions.");
+        } else if page.read_only == Some(true) {
+            let user = self.get_current_user().await?;
+            self.set_page_read_only(&page.page_id, &user).await?;
             debug!("set 'view only' for anyone else than current user");
         } else if page.read_only == Some(false) {
             self.remove_page_restriction(&page.page_id).await?;
             debug!("removing all page restrictions for users.");
         }
 
-        Ok(Some(response))
+        Ok(UpdateOutcome::Updated { version })
     }
 }
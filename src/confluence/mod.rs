@@ -1,5 +1,10 @@
+mod auth;
 mod client;
 mod page;
 mod restriction;
-pub use client::ConfluenceClient;
-pub use page::{filter_valid_labels, ConfluencePage};
+pub use auth::{AuthMethod, ClientOptions, Flavor};
+pub use client::{ConfluenceClient, UpdateOutcome};
+pub use page::{
+    apply_label_mode, is_valid_label, normalize_label, ConfluencePage, LabelMode, LABEL_MAX_LENGTH,
+    LABEL_PATTERN,
+};
@@ -1,23 +1,44 @@
 use crate::config::Page;
+use crate::error::{Error, Result};
+use clap::ValueEnum;
 use regex::Regex;
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::vec::Vec;
 use tracing::{instrument, warn, Level};
 
+/// Request body of the Confluence APIv1 content endpoint to update a page.
 #[derive(Serialize, Debug)]
 pub struct ConfluencePage {
+    /// The title of the page.
     pub title: String,
+    /// The content type, always `page`.
     #[serde(rename = "type")]
     pub type_field: String,
+    /// The status of the page, always `current`.
     pub status: String,
+    /// The new version number and message.
     pub version: Version,
+    /// The parent of the page, the page isn't moved if empty.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ancestors: Vec<Ancestor>,
+    /// The content in storage format.
     pub body: Body,
+    /// The labels of the page.
     pub metadata: Metadata,
 }
 
 #[derive(Serialize, Debug)]
 pub struct Version {
     pub number: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Setting the ancestors of a page moves it below the last ancestor.
+#[derive(Serialize, Debug)]
+pub struct Ancestor {
+    pub id: String,
 }
 
 #[derive(Serialize, Debug)]
@@ -48,6 +69,7 @@ impl From<String> for Label {
 }
 
 impl ConfluencePage {
+    /// The page as the given version, invalid labels are left out.
     #[instrument(skip_all, ret(level = Level::TRACE))]
     pub fn new(page: &Page, version: u64) -> Self {
         let labels = filter_valid_labels(&page.labels)
@@ -59,7 +81,15 @@ impl ConfluencePage {
             title: page.title.to_string(),
             type_field: "page".to_string(),
             status: "current".to_string(),
-            version: Version { number: version },
+            version: Version {
+                number: version,
+                message: page.version_message.clone(),
+            },
+            ancestors: page
+                .parent_id
+                .iter()
+                .map(|id| Ancestor { id: id.to_string() })
+                .collect(),
             body: Body {
                 storage: Storage {
                     value: page.html.html.to_string(),
@@ -70,6 +100,16 @@ impl ConfluencePage {
         }
     }
 
+    /// The names of the labels of the page.
+    pub fn label_names(&self) -> Vec<String> {
+        self.metadata
+            .labels
+            .iter()
+            .map(|x| x.name.to_string())
+            .collect()
+    }
+
+    /// Adds the labels to the page.
     pub fn add_labels(mut self, labels: Vec<String>) -> Self {
         self.metadata
             .labels
@@ -78,20 +118,86 @@ impl ConfluencePage {
     }
 }
 
-pub fn filter_valid_labels(labels: &[String]) -> Vec<String> {
-    let pattern = r"^[a-z0-9\$%'+\-/=\_`{}|~]+$";
-    let regex = Regex::new(pattern).expect("invalid regex patteren");
+/// Labels can only contain these characters, uppercase letters are lowercased by Confluence.
+pub const LABEL_PATTERN: &str = r"^[a-z0-9\$%'+\-/=\_`{}|~]+$";
+
+/// The maximum number of characters in a label.
+pub const LABEL_MAX_LENGTH: usize = 255;
+
+/// How labels not matching the label pattern are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelMode {
+    /// Invalid labels are left out with a warning.
+    #[default]
+    Filter,
+    /// Invalid labels are normalized with [`normalize_label`].
+    Normalize,
+    /// Invalid labels are an error.
+    Strict,
+}
 
-    let filter_labels = |label| match regex.is_match(label) {
+/// Whether Confluence accepts the label as is.
+pub fn is_valid_label(label: &str) -> bool {
+    let regex = Regex::new(LABEL_PATTERN).expect("invalid regex patteren");
+    regex.is_match(label) && label.chars().count() <= LABEL_MAX_LENGTH
+}
+
+/// Lowercases the label, replaces whitespace and invalid characters with `-` and truncates it to the max length.
+pub fn normalize_label(label: &str) -> String {
+    let regex = Regex::new(LABEL_PATTERN).expect("invalid regex patteren");
+
+    label
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|x| match regex.is_match(&x.to_string()) {
+            true => x,
+            false => '-',
+        })
+        .take(LABEL_MAX_LENGTH)
+        .collect()
+}
+
+/// Invalid labels are skipped in filter mode, normalized in normalize mode and results in an error in strict mode.
+pub fn apply_label_mode(labels: &[String], mode: LabelMode) -> Result<Vec<String>> {
+    match mode {
+        LabelMode::Filter => Ok(filter_valid_labels(labels)),
+        LabelMode::Normalize => {
+            let mut normalized: Vec<String> = vec![];
+            for label in labels.iter().map(|x| normalize_label(x)) {
+                if !label.is_empty() && !normalized.contains(&label) {
+                    normalized.push(label);
+                }
+            }
+            Ok(normalized)
+        }
+        LabelMode::Strict => {
+            let invalid: Vec<&str> = labels
+                .iter()
+                .filter(|x| !is_valid_label(x))
+                .map(|x| x.as_str())
+                .collect();
+
+            match invalid.is_empty() {
+                true => Ok(labels.to_vec()),
+                false => Err(Error::InvalidLabels(invalid.join(", "))),
+            }
+        }
+    }
+}
+
+pub fn filter_valid_labels(labels: &[String]) -> Vec<String> {
+    let filter_labels = |label: &String| match is_valid_label(label) {
         true => Some(label.to_string()),
         false => {
             warn!(
                 "invalid label [{}]. labels must match the following regex: {}, skipping..",
-                label, pattern
+                label, LABEL_PATTERN
             );
             None
         }
     };
 
-    labels.iter().filter_map(|x| filter_labels(x)).collect()
+    labels.iter().filter_map(filter_labels).collect()
 }
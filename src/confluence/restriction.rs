@@ -1,3 +1,4 @@
+use super::client;
 use serde::Serialize;
 
 #[derive(Debug, Serialize)]
@@ -14,37 +15,139 @@ struct Result {
 #[derive(Debug, Serialize)]
 struct Restrictions {
     pub user: Vec<User>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<Vec<Group>>,
 }
 
 #[derive(Debug, Serialize)]
+struct Group {
+    #[serde(rename = "type")]
+    type_field: String,
+    name: String,
+}
+
+/// Users and groups of a restriction, with the users resolved to Confluence accounts.
+#[derive(Debug, Default)]
+pub struct ResolvedPrincipals {
+    pub users: Vec<client::User>,
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 struct User {
     #[serde(rename = "type")]
     type_field: String,
-    account_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
 }
 
 impl Restriction {
-    pub fn read_only(account_id: &str) -> Self {
+    pub fn read_only(user: &client::User) -> Self {
         Self {
             results: vec![Result {
                 operation: "update".to_string(),
                 restrictions: Restrictions {
-                    user: vec![User {
-                        type_field: "known".to_string(),
-                        account_id: account_id.to_string(),
-                    }],
+                    user: vec![User::from(user)],
+                    group: None,
                 },
             }],
         }
     }
 
+    /// Restricts viewing and editing to the principals. The current user is always included, so the page can still
+    /// be updated. Users and groups allowed to edit are also allowed to view.
+    pub fn with_principals(
+        current_user: &client::User,
+        view: Option<ResolvedPrincipals>,
+        edit: Option<ResolvedPrincipals>,
+    ) -> Self {
+        let view = view.map(|mut view| {
+            if let Some(edit) = &edit {
+                view.users.extend(edit.users.iter().cloned());
+                view.groups.extend(edit.groups.iter().cloned());
+            }
+            view
+        });
+
+        Self {
+            results: vec![
+                Result::new("read", current_user, view),
+                Result::new("update", current_user, edit),
+            ],
+        }
+    }
+
     pub fn no_restrictions() -> Self {
         Self {
             results: vec![Result {
                 operation: "update".to_string(),
-                restrictions: Restrictions { user: vec![] },
+                restrictions: Restrictions {
+                    user: vec![],
+                    group: None,
+                },
             }],
         }
     }
 }
+
+impl Result {
+    // An operation without principals is not restricted.
+    fn new(
+        operation: &str,
+        current_user: &client::User,
+        principals: Option<ResolvedPrincipals>,
+    ) -> Self {
+        let Some(principals) = principals else {
+            return Self {
+                operation: operation.to_string(),
+                restrictions: Restrictions {
+                    user: vec![],
+                    group: Some(vec![]),
+                },
+            };
+        };
+
+        let mut users: Vec<User> = vec![User::from(current_user)];
+        for user in principals.users.iter().map(User::from) {
+            if !users.contains(&user) {
+                users.push(user);
+            }
+        }
+
+        let mut groups: Vec<String> = vec![];
+        for group in principals.groups {
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+
+        Self {
+            operation: operation.to_string(),
+            restrictions: Restrictions {
+                user: users,
+                group: Some(
+                    groups
+                        .into_iter()
+                        .map(|name| Group {
+                            type_field: "group".to_string(),
+                            name,
+                        })
+                        .collect(),
+                ),
+            },
+        }
+    }
+}
+
+impl From<&client::User> for User {
+    fn from(user: &client::User) -> Self {
+        Self {
+            type_field: "known".to_string(),
+            account_id: user.account_id.clone(),
+            username: user.username.clone(),
+        }
+    }
+}
@@ -1,37 +1,193 @@
+/// Errors of the crate, their messages are shown to the user.
 #[derive(thiserror::Error, Debug)]
 #[allow(clippy::enum_variant_names)]
 pub enum Error {
+    /// A request to Confluence failed or returned an error status.
     #[error("HTTP request, {0}")]
     Reqwest(#[from] reqwest::Error),
 
+    /// Reading or writing a file failed.
     #[error("Could not read the config file: {0}")]
     IO(#[from] std::io::Error),
 
+    /// Formatting the rendered page failed.
     #[error("std:fmt:Error: {0}")]
     Fmt(#[from] std::fmt::Error),
 
+    /// The YAML of a config file is invalid.
     #[error("Cound not parse YAML in config file: {0}")]
     SerdeYml(#[from] serde_yml::Error),
 
+    /// The content of a config file in the given format doesn't match the config file schema.
+    #[error("Cound not parse {0} in config file: {1}")]
+    ConfigContent(&'static str, serde_yml::Error),
+
+    /// A config file has an unknown field, with the closest known field.
+    #[error("Cound not parse {0} in config file: {1}. Did you mean `{2}`?")]
+    UnknownConfigField(&'static str, serde_yml::Error, String),
+
+    /// Serializing or deserializing JSON failed, e.g. the cache file.
+    #[error("Could not serialize JSON: {0}")]
+    SerdeJson(#[from] serde_json::Error),
+
+    /// A number couldn't be parsed.
     #[error("Faild to parse string to interger: {0}")]
     ParseInt(#[from] std::num::ParseIntError),
 
+    /// The output of a command isn't valid UTF-8.
     #[error("Cound not parse UTF-8 byte vector to String: {0}")]
     FromUtf8(#[from] std::string::FromUtf8Error),
 
+    /// The page has neither a title nor an h1 header.
     #[error(
         "No h1 header found on top of page. Add a header or use the overrideTitle configuration"
     )]
     PageHeaderMissing,
 
+    /// A Confluence Cloud URL doesn't start with `https://`.
     #[error("HTTPS protocol scheme missing from FQDN: [{0}]")]
     HttpsProtocolSchemeMissing(String),
 
+    /// A Confluence Data Center URL doesn't start with `https://` or `http://`.
+    #[error("HTTP or HTTPS protocol scheme missing from FQDN: [{0}]")]
+    ProtocolSchemeMissing(String),
+
+    /// The secret couldn't be read from its source.
+    #[error("Could not read the secret: {0}")]
+    SecretUnavailable(String),
+
+    /// No secret is given for the command line instance.
+    #[error(
+        "A secret is required, use --secret, --secret-file, --secret-stdin or --credential-helper."
+    )]
+    SecretMissing,
+
+    /// No URL is given for the command line instance.
+    #[error("An FQDN is required for pages without an instance, use --fqdn.")]
+    FqdnMissing,
+
+    /// A page or prune scope uses an instance that isn't defined.
+    #[error("Instance is not defined in the config file: [{0}]")]
+    UnknownInstance(String),
+
+    /// A config file defines an instance with the reserved name.
+    #[error("Instance name is reserved for the command line instance: [{0}]")]
+    InvalidInstance(String),
+
+    /// More than one config file defines the instance.
+    #[error("Instance is defined in more than one config file: [{0}]")]
+    DuplicateInstance(String),
+
+    /// A config file couldn't be loaded.
+    #[error("Invalid config file [{path}]: {source}")]
+    InvalidConfigFile {
+        /// Path of the config file.
+        path: String,
+        /// Why the config file couldn't be loaded.
+        source: Box<Error>,
+    },
+
+    /// The TOML of a config file is invalid.
+    #[error("Cound not parse TOML in config file: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    /// The JSON of a config file is invalid.
+    #[error("Cound not parse JSON in config file: {0}")]
+    Json(serde_json::Error),
+
+    /// A filter doesn't match any page.
+    #[error("No pages match the filter: [{0}]")]
+    UnknownFilter(String),
+
+    /// Labels are invalid in strict label mode.
+    #[error("Invalid label(s), labels must match the regex {pattern} and be at most {max} characters: [{0}]", pattern = crate::confluence::LABEL_PATTERN, max = crate::confluence::LABEL_MAX_LENGTH)]
+    InvalidLabels(String),
+
+    /// A filter is invalid, e.g. a malformed glob pattern.
+    #[error("Invalid filter: [{0}]")]
+    InvalidFilter(String),
+
+    /// Basic authentication is used without a user.
+    #[error("A user is required when using basic authentication.")]
+    UserMissing,
+
+    /// OAuth authentication is used without a client id.
+    #[error("An OAuth client id is required when using OAuth authentication.")]
+    OAuthClientIdMissing,
+
+    /// OAuth authentication is used with Confluence Data Center.
+    #[error("OAuth authentication is only supported by Confluence Cloud.")]
+    OAuthNotSupported,
+
+    /// A file doesn't exist or its path is invalid.
     #[error("File path is invalid: [{0}]")]
     InvalidFilePath(String),
 
+    /// The current user has neither an email, username nor account id.
     #[error("Failed to get the local part of the current user email.")]
     CurrentUserEmailMissing,
+
+    /// A git command failed.
+    #[error("Git command failed: {0}")]
+    Git(String),
+
+    /// Markdown files include each other.
+    #[error("Include directives form a cycle: [{0}]")]
+    IncludeCycle(String),
+
+    /// An included Markdown file doesn't exist.
+    #[error("Included file not found: [{path}], included from [{included_from}]")]
+    IncludeMissing {
+        /// Path of the included file.
+        path: String,
+        /// Path of the file with the include directive.
+        included_from: String,
+    },
+
+    /// The Markdown uses template variables that aren't defined.
+    #[error("Undefined template variable(s): [{0}]")]
+    UndefinedVariable(String),
+
+    /// A config file uses environment variables that aren't set.
+    #[error("Environment variable(s) not set and without a default value: [{0}]")]
+    EnvVariableMissing(String),
+
+    /// The file generated by `init` already exists.
+    #[error("File already exists, use --force to overwrite it: [{0}]")]
+    OutputFileExists(String),
+
+    /// A page has neither a page ID nor a space.
+    #[error("A page needs either a pageId or a space to look up the page by title: [{0}]")]
+    PageIdMissing(String),
+
+    /// A page looked up by space has no title.
+    #[error("A title is required to look up a page without a pageId by space and title: [{0}]")]
+    PageTitleMissing(String),
+
+    /// No page with the title exists in the space.
+    #[error("No page found, set createMissing to create it: [{0}]")]
+    PageNotFound(String),
+
+    /// More than one page with the title exists in the space.
+    #[error("More than one page found, set the pageId or a parent: [{0}]")]
+    AmbiguousPage(String),
+
+    /// Pruning is requested without a prune scope.
+    #[error("Pruning requires a prune scope in the config file.")]
+    PruneScopeMissing,
+
+    /// Pruning is requested for a config file without an owner.
+    #[error("Pruning requires an owner, set owner in the config file or run in a git repository with an origin remote.")]
+    PruneOwnerMissing,
+
+    /// Archiving is requested on Confluence Data Center.
+    #[error("Archiving pages is only supported by Confluence Cloud, use another prune mode.")]
+    ArchiveNotSupported,
+
+    /// A restriction user on Confluence Cloud is an email instead of an account id.
+    #[error("Restriction users must be account ids on Confluence Cloud, emails can't be looked up: [{0}]")]
+    EmailPrincipal(String),
 }
 
+/// Result with the crate [`Error`].
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,174 @@
+use crate::config::PageConfig;
+use crate::error::{Error, Result};
+use crate::git;
+use crate::render_markdown;
+use glob::Pattern;
+use normalize_path::NormalizePath;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, instrument, span, warn, Level};
+
+// ###################################################### //
+//                   PageFilter Struct                    //
+// ###################################################### //
+
+// Restricts which pages are processed. A page must match every kind of filter given, and any value within a kind.
+#[derive(Debug, Default)]
+pub struct PageFilter {
+    page_ids: Vec<String>,
+    file_patterns: Vec<Pattern>,
+    tags: Vec<String>,
+}
+
+impl PageFilter {
+    #[instrument(skip_all, err(Display))]
+    pub fn new(page_ids: &[String], file_globs: &[String], tags: &[String]) -> Result<Self> {
+        let file_patterns = file_globs
+            .iter()
+            .map(|x| {
+                Pattern::new(&normalize(x))
+                    .map_err(|error| Error::InvalidFilter(format!("{x}: {error}")))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            page_ids: page_ids.to_vec(),
+            file_patterns,
+            tags: tags.to_vec(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.page_ids.is_empty() && self.file_patterns.is_empty() && self.tags.is_empty()
+    }
+
+    // Returns the pages matching the filter. Filters matching none of the configured pages results in an error.
+    #[instrument(skip_all, err(Display))]
+    pub fn apply(&self, pages: Vec<PageConfig>) -> Result<Vec<PageConfig>> {
+        if self.is_empty() {
+            return Ok(pages);
+        }
+
+        self.check_unknown(&pages)?;
+
+        let pages = pages
+            .into_iter()
+            .filter(|page| self.matches(page))
+            .collect::<Vec<_>>();
+
+        debug!(count = pages.len(), "pages matching filter");
+        Ok(pages)
+    }
+
+    #[instrument(skip_all, ret(level = Level::TRACE), fields(id = page.page_id, path = page.file_path))]
+    fn matches(&self, page: &PageConfig) -> bool {
+        let tags = page.tags.as_deref().unwrap_or_default();
+        let path = normalize(&page.file_path);
+
+        let id_match = self.page_ids.is_empty()
+            || page
+                .page_id
+                .as_ref()
+                .is_some_and(|x| self.page_ids.contains(x));
+        let file_match =
+            self.file_patterns.is_empty() || self.file_patterns.iter().any(|x| x.matches(&path));
+        let tag_match = self.tags.is_empty() || self.tags.iter().any(|x| tags.contains(x));
+
+        id_match && file_match && tag_match
+    }
+
+    fn check_unknown(&self, pages: &[PageConfig]) -> Result<()> {
+        for page_id in &self.page_ids {
+            if !pages.iter().any(|x| x.page_id.as_ref() == Some(page_id)) {
+                return Err(Error::UnknownFilter(format!("page id {page_id}")));
+            }
+        }
+
+        for pattern in &self.file_patterns {
+            if !pages
+                .iter()
+                .any(|x| pattern.matches(&normalize(&x.file_path)))
+            {
+                return Err(Error::UnknownFilter(format!("file {pattern}")));
+            }
+        }
+
+        for tag in &self.tags {
+            if !pages
+                .iter()
+                .any(|x| x.tags.as_deref().unwrap_or_default().contains(tag))
+            {
+                return Err(Error::UnknownFilter(format!("tag {tag}")));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// ###################################################### //
+//               Changed Since Git Ref Filter             //
+// ###################################################### //
+
+// Returns the pages where the markdown file, included files or images changed since the git ref.
+// All pages are returned if a config file changed, since any page property might have changed.
+#[instrument(skip(pages, config_paths), err(Display))]
+pub fn changed_since(
+    pages: Vec<PageConfig>,
+    git_ref: &str,
+    config_paths: &[String],
+    fallback: bool,
+) -> Result<Vec<PageConfig>> {
+    let changed_files = match git::changed_files(git_ref) {
+        Ok(files) => files,
+        Err(error) if fallback => {
+            warn!(%error, "could not get changed files, falling back to SHA check for all pages");
+            return Ok(pages);
+        }
+        Err(error) => return Err(error),
+    };
+
+    if config_paths.iter().any(|x| is_changed(x, &changed_files)) {
+        info!("config file changed, checking all pages.");
+        return Ok(pages);
+    }
+
+    let pages = pages
+        .into_iter()
+        .filter(|page| {
+            let span = span!(
+                Level::INFO,
+                "page",
+                id = page.page_id,
+                path = page.file_path
+            );
+            let _enter = span.enter();
+
+            // Pages with unreadable sources are kept, so the error surfaces when the page is rendered.
+            let changed = match render_markdown::source_paths(page) {
+                Ok(paths) => paths.iter().any(|x| is_changed(x, &changed_files)),
+                Err(_) => true,
+            };
+
+            if !changed {
+                info!("no changes since {git_ref}, skipping.");
+            }
+
+            changed
+        })
+        .collect();
+
+    Ok(pages)
+}
+
+fn is_changed(path: &str, changed_files: &HashSet<PathBuf>) -> bool {
+    match Path::new(path).canonicalize() {
+        Ok(full_path) => changed_files.contains(&full_path),
+        Err(_) => true,
+    }
+}
+
+// Normalizes paths, so `./docs/file.md` and `docs/file.md` are matched alike.
+fn normalize(path: &str) -> String {
+    Path::new(path).normalize().to_string_lossy().to_string()
+}
@@ -0,0 +1,196 @@
+use crate::error::{Error, Result};
+use normalize_path::NormalizePath;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, instrument, Level};
+
+// ###################################################### //
+//                     Commit Struct                      //
+// ###################################################### //
+
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub sha: String,
+    pub author: String,
+    pub date: String,
+    pub subject: String,
+}
+
+impl Commit {
+    // Reads the commit currently checked out in the local repository.
+    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    pub fn head() -> Result<Self> {
+        let output = git(&["log", "-1", LOG_FORMAT])?;
+        Self::from_log_output(&output)
+    }
+
+    // Reads the latest commit that modified the given path, falling back to HEAD if the path is not committed yet.
+    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    pub fn last_for_path(path: &str) -> Result<Self> {
+        let output = git(&["log", "-1", LOG_FORMAT, "--", path])?;
+
+        match output.is_empty() {
+            true => Self::head(),
+            false => Self::from_log_output(&output),
+        }
+    }
+
+    fn from_log_output(output: &str) -> Result<Self> {
+        let mut lines = output.splitn(4, '\n');
+        let mut next_line = || {
+            lines
+                .next()
+                .map(|x| x.to_string())
+                .ok_or_else(|| Error::Git(format!("unexpected git log output: [{output}]")))
+        };
+
+        Ok(Self {
+            sha: next_line()?,
+            author: next_line()?,
+            date: next_line()?,
+            subject: next_line()?,
+        })
+    }
+
+    // Reads the commit from the environment variables set by common CI systems.
+    #[instrument(skip_all, ret(level = Level::TRACE))]
+    pub fn from_ci_env() -> Option<Self> {
+        let env = |name: &str| std::env::var(name).ok().filter(|x| !x.is_empty());
+
+        // (sha variable, subject variable) for GitHub Actions, GitLab CI, Azure Pipelines and Bitbucket Pipelines.
+        let ci_variables = [
+            ("GITHUB_SHA", None),
+            ("CI_COMMIT_SHA", Some("CI_COMMIT_TITLE")),
+            ("BUILD_SOURCEVERSION", Some("BUILD_SOURCEVERSIONMESSAGE")),
+            ("BITBUCKET_COMMIT", None),
+        ];
+
+        ci_variables.iter().find_map(|(sha_var, subject_var)| {
+            let sha = env(sha_var)?;
+            let subject = subject_var.and_then(env).unwrap_or_default();
+            debug!(sha_var, "found commit sha in CI environment variable");
+            Some(Self {
+                sha,
+                author: String::new(),
+                date: String::new(),
+                subject,
+            })
+        })
+    }
+
+    // Formats the commit as a Confluence page version message.
+    pub fn version_message(&self) -> String {
+        format!("{} {}", self.sha, self.subject).trim().to_string()
+    }
+}
+
+// ###################################################### //
+//            Helper functions for running git            //
+// ###################################################### //
+
+// Commit SHA, author name, strict ISO 8601 author date and subject, separated by newlines.
+const LOG_FORMAT: &str = "--format=%H%n%an%n%aI%n%s";
+
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|error| Error::Git(error.to_string()))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(Error::Git(stderr));
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+// Resolves the version message in the following order: explicit message, local git repository, CI environment variables.
+#[instrument(skip_all, ret(level = Level::TRACE))]
+pub fn resolve_version_message(message: Option<&str>) -> Option<String> {
+    if let Some(message) = message {
+        return Some(message.to_string());
+    }
+
+    match Commit::head() {
+        Ok(commit) => Some(commit.version_message()),
+        Err(error) => {
+            debug!(%error, "could not read commit from local git repository");
+            Commit::from_ci_env().map(|commit| commit.version_message())
+        }
+    }
+}
+
+// Returns the web URL of the `origin` remote, converting SSH remotes to HTTPS.
+#[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+pub fn remote_url() -> Result<String> {
+    let remote = git(&["remote", "get-url", "origin"])?;
+    Ok(web_url(&remote))
+}
+
+// Credentials in the remote, like the job token GitLab CI clones with, must never end up in a page.
+fn web_url(remote: &str) -> String {
+    let (scheme, authority, path) = match remote.split_once("://") {
+        Some(("file", _)) => return remote.to_string(),
+        Some((scheme, rest)) => {
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            (scheme, authority, path)
+        }
+        // The scp-like syntax of SSH remotes, `[user@]host:path`, or a local path.
+        None => match remote.split_once(':') {
+            Some((authority, path)) => ("ssh", authority, path),
+            None => return remote.to_string(),
+        },
+    };
+
+    let host = authority.rsplit_once('@').map_or(authority, |x| x.1);
+
+    let url = match scheme {
+        "http" | "https" => format!("{scheme}://{host}/{path}"),
+        // SSH remotes are served over HTTPS, the SSH port isn't the port of the web server.
+        _ => {
+            let host = host
+                .rsplit_once(':')
+                .filter(|(_, port)| !port.contains(']'))
+                .map_or(host, |x| x.0);
+            format!("https://{host}/{path}")
+        }
+    };
+
+    url.trim_end_matches('/')
+        .trim_end_matches(".git")
+        .to_string()
+}
+
+// Returns the path of a file relative to the root of the repository it is part of.
+#[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+pub fn repo_relative_path(path: &str) -> Result<String> {
+    let toplevel = Path::new(&git(&["rev-parse", "--show-toplevel"])?).canonicalize()?;
+    let full_path = Path::new(path).canonicalize()?;
+
+    full_path
+        .strip_prefix(&toplevel)
+        .ok()
+        .and_then(|x| x.to_str())
+        .map(|x| x.replace('\\', "/"))
+        .ok_or_else(|| Error::Git(format!("file is not part of the git repository: [{path}]")))
+}
+
+// Returns the full paths of all files changed between the git ref and the working tree, including untracked files.
+#[instrument(skip_all, fields(git_ref = git_ref), ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+pub fn changed_files(git_ref: &str) -> Result<HashSet<PathBuf>> {
+    let toplevel = Path::new(&git(&["rev-parse", "--show-toplevel"])?).canonicalize()?;
+
+    let changed = git(&["diff", "--name-only", git_ref, "--"])?;
+    let untracked = git(&["ls-files", "--others", "--exclude-standard", "--full-name"])?;
+
+    let files = changed
+        .lines()
+        .chain(untracked.lines())
+        .filter(|x| !x.is_empty())
+        .map(|x| toplevel.join(x).normalize())
+        .collect();
+
+    Ok(files)
+}
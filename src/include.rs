@@ -0,0 +1,166 @@
+use crate::error::{Error, Result};
+use crate::template;
+use normalize_path::NormalizePath;
+use regex::{Captures, Regex};
+use std::path::{Path, PathBuf};
+use tracing::{debug, instrument, Level};
+
+// ###################################################### //
+//                 Expanded Markdown Struct               //
+// ###################################################### //
+
+#[derive(Debug)]
+pub struct ExpandedMarkdown {
+    pub markdown: String,
+    pub included_paths: Vec<String>,
+}
+
+impl ExpandedMarkdown {
+    // Reads a markdown file and recursively replaces all `<!-- include: path -->` directives with the content of the file.
+    #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+    pub fn new(file_path: &str) -> Result<Self> {
+        let mut included_paths = vec![];
+        let mut stack = vec![];
+        let markdown = expand(Path::new(file_path), &mut stack, &mut included_paths)?;
+
+        Ok(Self {
+            markdown,
+            included_paths,
+        })
+    }
+}
+
+// ###################################################### //
+//       Helper functions for expanding include files     //
+// ###################################################### //
+
+// Expands the includes of a single file. Image paths of the returned markdown are relative to the file itself.
+fn expand(
+    file_path: &Path,
+    stack: &mut Vec<PathBuf>,
+    included: &mut Vec<String>,
+) -> Result<String> {
+    let normalized_path = file_path.normalize();
+
+    if stack.contains(&normalized_path) {
+        let chain = stack
+            .iter()
+            .chain([&normalized_path])
+            .map(|x| x.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        return Err(Error::IncludeCycle(chain));
+    }
+
+    if !file_path.is_file() {
+        // A missing include is reported together with the file including it.
+        return match stack.last() {
+            Some(included_from) => Err(Error::IncludeMissing {
+                path: normalized_path.display().to_string(),
+                included_from: included_from.display().to_string(),
+            }),
+            None => Err(Error::InvalidFilePath(file_path.display().to_string())),
+        };
+    }
+
+    let markdown = std::fs::read_to_string(file_path)?;
+    stack.push(normalized_path);
+
+    let regex = Regex::new(r"(?m)^[ \t]*<!--\s*include:\s*(\S.*?)\s*-->[ \t]*$")
+        .expect("invalid regex patteren");
+
+    let mut result = String::new();
+    let mut last_match = 0;
+
+    // Directives in code blocks are kept, so the include syntax itself can be documented.
+    let code_ranges = template::code_ranges(&markdown);
+
+    for captures in regex.captures_iter(&markdown) {
+        let directive = captures.get(0).expect("capture group 0 always exists");
+
+        if template::is_code(&code_ranges, directive.start()) {
+            continue;
+        }
+
+        let include_path = file_path.with_file_name(&captures[1]);
+        debug!(include = %include_path.display(), "found include directive");
+
+        let content = expand(&include_path, stack, included)?;
+        let include_dir = include_path.parent().unwrap_or(Path::new(""));
+        let file_dir = file_path.parent().unwrap_or(Path::new(""));
+
+        result.push_str(&markdown[last_match..directive.start()]);
+        result.push_str(&rebase_image_paths(&content, include_dir, file_dir));
+        last_match = directive.end();
+
+        let include_path = include_path.normalize().display().to_string();
+        if !included.contains(&include_path) {
+            included.push(include_path);
+        }
+    }
+
+    result.push_str(&markdown[last_match..]);
+    stack.pop();
+
+    Ok(result)
+}
+
+// Rewrites relative image paths in the markdown from being relative to `from_dir` to being relative to `to_dir`.
+// Inline images, reference definitions and HTML images are rewritten, paths in code are kept.
+fn rebase_image_paths(markdown: &str, from_dir: &Path, to_dir: &Path) -> String {
+    let regex = Regex::new(concat!(
+        r"(!\[[^\]]*\]\(\s*)([^)\s]+)",
+        r"|(?m)(^[ ]{0,3}\[[^\]]+\]:[ \t]*<?)([^\s>]+)",
+        r#"|(<img\s[^>]*?\bsrc\s*=\s*["']?)([^"'\s>]+)"#,
+    ))
+    .expect("invalid regex patteren");
+
+    let code_ranges = template::code_ranges(markdown);
+
+    regex
+        .replace_all(markdown, |captures: &Captures| {
+            let matched = captures.get(0).expect("capture group 0 always exists");
+            let (prefix, url) = match (captures.get(1), captures.get(3), captures.get(5)) {
+                (Some(prefix), _, _) => (prefix.as_str(), &captures[2]),
+                (_, Some(prefix), _) => (prefix.as_str(), &captures[4]),
+                (_, _, Some(prefix)) => (prefix.as_str(), &captures[6]),
+                _ => return matched.as_str().to_string(),
+            };
+
+            if url.starts_with("https://")
+                || url.starts_with("http://")
+                || url.starts_with('#')
+                || url.contains(':')
+                || Path::new(url).has_root()
+                || template::is_code(&code_ranges, matched.start())
+            {
+                return matched.as_str().to_string();
+            }
+
+            let image_path = relative_path(&from_dir.join(url), to_dir);
+            format!("{prefix}{}", image_path.display())
+        })
+        .into_owned()
+}
+
+// Returns `path` relative to `base`. Both paths must be relative to the same directory.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path = path.normalize();
+    let base = base.normalize();
+
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    base.components()
+        .skip(common)
+        .for_each(|_| relative.push(".."));
+    path.components()
+        .skip(common)
+        .for_each(|x| relative.push(x));
+
+    relative
+}
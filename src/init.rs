@@ -0,0 +1,226 @@
+use crate::confluence::ConfluenceClient;
+use crate::error::{Error, Result};
+use crate::include::ExpandedMarkdown;
+use crate::render_markdown;
+use glob::MatchOptions;
+use normalize_path::NormalizePath;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use tracing::{info, instrument, span, warn, Level};
+
+// ###################################################### //
+//                  Scaffolded Page Struct                //
+// ###################################################### //
+
+#[derive(Debug)]
+struct ScaffoldedPage {
+    file_path: String,
+    title: Option<String>,
+    page_id: Option<String>,
+}
+
+// ###################################################### //
+//                    Init Functions                      //
+// ###################################################### //
+
+/// Scans a directory for markdown files and writes a config file with a page for each of them.
+/// If a client and space key is given, page ids are looked up by the title of the page.
+#[instrument(skip(client), err(Display))]
+pub async fn run(
+    dir: &str,
+    output: &str,
+    force: bool,
+    space_key: Option<&str>,
+    client: Option<&ConfluenceClient>,
+) -> Result<()> {
+    if Path::new(output).exists() && !force {
+        return Err(Error::OutputFileExists(output.to_string()));
+    }
+
+    let mut files = vec![];
+    let mut included_paths = HashSet::new();
+
+    for file_path in find_markdown_files(dir)? {
+        let markdown = match ExpandedMarkdown::new(&file_path) {
+            Ok(expanded) => {
+                included_paths.extend(expanded.included_paths);
+                expanded.markdown
+            }
+            Err(error) => {
+                warn!(path = file_path, %error, "could not expand the includes of the file.");
+                std::fs::read_to_string(&file_path)?
+            }
+        };
+
+        files.push((file_path, markdown));
+    }
+
+    // Files included by other files are part of those pages and don't get a page of their own.
+    let output_dir = Path::new(output).parent().unwrap_or(Path::new(""));
+    let mut pages = vec![];
+
+    for (file_path, markdown) in files {
+        if included_paths.contains(&Path::new(&file_path).normalize().display().to_string()) {
+            info!(path = file_path, "skipping included file.");
+            continue;
+        }
+
+        let span = span!(Level::INFO, "page", path = file_path);
+        let _enter = span.enter();
+
+        let title = render_markdown::page_header(&markdown);
+
+        let page_id = match (client, space_key, &title) {
+            (Some(client), Some(space_key), Some(title)) => {
+                find_page_id(client, space_key, title).await?
+            }
+            _ => None,
+        };
+
+        pages.push(ScaffoldedPage {
+            file_path: relative_path(output_dir, Path::new(&file_path))?,
+            title,
+            page_id,
+        });
+    }
+
+    std::fs::create_dir_all(output_dir)?;
+    std::fs::write(output, render_config(&pages, space_key))?;
+    info!(pages = pages.len(), "config file written to {output}.");
+
+    Ok(())
+}
+
+// Returns the paths of all markdown files in the directory and its subdirectories, skipping hidden files.
+fn find_markdown_files(dir: &str) -> Result<Vec<String>> {
+    let pattern = Path::new(dir).join("**").join("*.md");
+    let pattern = pattern
+        .to_str()
+        .ok_or(Error::InvalidFilePath(dir.to_string()))?;
+
+    let options = MatchOptions {
+        require_literal_leading_dot: true,
+        ..MatchOptions::new()
+    };
+
+    let paths = glob::glob_with(pattern, options)
+        .map_err(|_| Error::InvalidFilePath(dir.to_string()))?
+        .filter_map(|x| x.ok())
+        .filter(|x| x.is_file())
+        .map(|x| x.display().to_string())
+        .collect();
+
+    Ok(paths)
+}
+
+async fn find_page_id(
+    client: &ConfluenceClient,
+    space_key: &str,
+    title: &str,
+) -> Result<Option<String>> {
+    let mut pages = client.find_pages(space_key, title).await?;
+
+    match pages.len() {
+        1 => Ok(pages.pop().map(|x| x.id)),
+        0 => {
+            warn!("no page found with the title [{title}] in space [{space_key}].");
+            Ok(None)
+        }
+        _ => {
+            warn!("more than one page found with the title [{title}] in space [{space_key}].");
+            Ok(None)
+        }
+    }
+}
+
+// File paths in the config are relative to the config file, so the path is made relative to the output directory.
+fn relative_path(base_dir: &Path, path: &Path) -> Result<String> {
+    let base_dir = std::path::absolute(base_dir)?.normalize();
+    let path = std::path::absolute(path)?.normalize();
+
+    let base_components = base_dir.components().collect::<Vec<_>>();
+    let path_components = path.components().collect::<Vec<_>>();
+    let common = base_components
+        .iter()
+        .zip(&path_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let relative = std::iter::repeat_n(Component::ParentDir, base_components.len() - common)
+        .chain(path_components[common..].iter().copied())
+        .collect::<PathBuf>();
+
+    match relative.starts_with("..") {
+        true => Ok(relative.display().to_string()),
+        false => Ok(format!("./{}", relative.display())),
+    }
+}
+
+fn render_config(pages: &[ScaffoldedPage], space_key: Option<&str>) -> String {
+    let mut config = String::from(
+        r#"# Generated by `confluence-updater init`.
+# Fill in the missing page IDs, then check the config with `confluence-updater validate`.
+
+# Restrict editing of all pages to the token owner.
+# readOnly: true
+
+# A superscript header added to the top of all pages.
+# superscriptHeader: This page is generated from Markdown, changes will be overwritten.
+
+"#,
+    );
+
+    match pages.is_empty() {
+        true => config
+            .push_str("pages: []\n  # - filePath: ./README.md\n  #   pageId: \"1234567890\"\n"),
+        false => config.push_str("pages:\n"),
+    }
+
+    for page in pages {
+        let comment = match (&page.title, &page.page_id, space_key) {
+            (Some(title), Some(_), _) => title.to_string(),
+            (Some(title), None, Some(space_key)) => format!(
+                "{title} - no unique page found in space {space_key}, set the page ID or createMissing."
+            ),
+            (Some(title), None, None) => {
+                format!("{title} - fill in the page ID, or a space to look up the page by title.")
+            }
+            (None, _, _) => {
+                "No h1 header found, fill in the page ID and set overrideTitle.".to_string()
+            }
+        };
+
+        config.push_str(&format!("  # {comment}\n"));
+        config.push_str(&format!("  - filePath: {}\n", yaml_string(&page.file_path)));
+
+        // Pages without a page ID are looked up by title if a space is given, the page ID is left to fill in otherwise.
+        match (&page.page_id, space_key, &page.title) {
+            (Some(page_id), _, _) => {
+                config.push_str(&format!("    pageId: {}\n", yaml_string(page_id)))
+            }
+            (None, Some(space_key), Some(title)) => {
+                config.push_str(&format!("    space: {}\n", yaml_string(space_key)));
+                config.push_str(&format!("    title: {}\n", yaml_string(title)));
+                config.push_str("    # createMissing: true\n");
+            }
+            (None, _, _) => config.push_str("    # pageId: \"1234567890\"\n"),
+        }
+
+        if page.title.is_none() {
+            config.push_str("    # overrideTitle: Page Title\n");
+        }
+    }
+
+    config
+}
+
+// JSON strings are valid YAML. Quotes the value unless it only contains characters that are safe in a plain YAML string.
+fn yaml_string(value: &str) -> String {
+    let is_plain = |x: char| x.is_ascii_alphanumeric() || "./_-".contains(x);
+    let is_number = value.chars().all(|x| x.is_ascii_digit());
+
+    match !value.is_empty() && !is_number && value.chars().all(is_plain) {
+        true => value.to_string(),
+        false => serde_json::to_string(value).unwrap_or_default(),
+    }
+}
@@ -0,0 +1,87 @@
+use crate::error::{Error, Result};
+use regex::{Captures, Regex};
+use serde_yml::Value;
+use std::collections::BTreeSet;
+use tracing::{instrument, Level};
+
+fn placeholder_regex() -> Regex {
+    Regex::new(r"\$\$|\$\{([A-Za-z_][A-Za-z0-9_]*)(?:(:?-)([^}]*))?\}")
+        .expect("invalid regex patteren")
+}
+
+// Replaces all `${VAR}`, `${VAR:-default}` and `${VAR-default}` placeholders in the string values of a YAML
+// document with environment variables, following shell semantics: `:-` uses the default if the variable is unset
+// or empty, `-` only if it's unset. `$$` escapes a literal `$`. Unset variables without a default results in an error.
+#[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+pub fn interpolate_yaml(yaml: &str) -> Result<String> {
+    // Documents without placeholders are returned as is, so parse errors keep their line numbers.
+    if !placeholder_regex().is_match(yaml) {
+        return Ok(yaml.to_string());
+    }
+
+    let mut value: Value = serde_yml::from_str(yaml)?;
+    let mut missing = BTreeSet::new();
+
+    interpolate_value(&mut value, &mut missing);
+
+    if !missing.is_empty() {
+        let names = missing.into_iter().collect::<Vec<_>>().join(", ");
+        return Err(Error::EnvVariableMissing(names));
+    }
+
+    Ok(serde_yml::to_string(&value)?)
+}
+
+fn interpolate_value(value: &mut Value, missing: &mut BTreeSet<String>) {
+    match value {
+        Value::String(text) => *value = interpolate_string(text, missing),
+        Value::Sequence(sequence) => sequence
+            .iter_mut()
+            .for_each(|x| interpolate_value(x, missing)),
+        Value::Mapping(mapping) => mapping
+            .values_mut()
+            .for_each(|x| interpolate_value(x, missing)),
+        Value::Tagged(tagged) => interpolate_value(&mut tagged.value, missing),
+        Value::Null | Value::Bool(_) | Value::Number(_) => (),
+    }
+}
+
+// Interpolates a single string. A value consisting only of a placeholder keeps the YAML type of the
+// variable, so `readOnly: ${READ_ONLY}` becomes a boolean.
+fn interpolate_string(text: &str, missing: &mut BTreeSet<String>) -> Value {
+    let regex = placeholder_regex();
+
+    let interpolated = regex.replace_all(text, |captures: &Captures| {
+        let Some(name) = captures.get(1) else {
+            return "$".to_string();
+        };
+
+        let env_value = std::env::var(name.as_str()).ok();
+        let env_value = match captures.get(2).map(|x| x.as_str()) {
+            Some(":-") => env_value.filter(|x| !x.is_empty()),
+            _ => env_value,
+        };
+
+        match (env_value, captures.get(3)) {
+            (Some(env_value), _) => env_value,
+            (None, Some(default)) => default.as_str().to_string(),
+            (None, None) => {
+                missing.insert(name.as_str().to_string());
+                captures[0].to_string()
+            }
+        }
+    });
+
+    let is_single_placeholder = regex
+        .find(text)
+        .is_some_and(|x| x.start() == 0 && x.end() == text.len() && x.as_str() != "$$");
+
+    if is_single_placeholder {
+        if let Ok(scalar @ (Value::Bool(_) | Value::Number(_))) = serde_yml::from_str(&interpolated)
+        {
+            return scalar;
+        }
+    }
+
+    Value::String(interpolated.into_owned())
+}
@@ -0,0 +1,77 @@
+//! Renders Markdown files to Confluence storage format and publishes them as Confluence pages.
+//!
+//! The `confluence-updater` binary is a command line interface on top of this library. The library can be used
+//! to render and publish pages from other tools, either from config files with [`config::Config`] or page by page.
+//!
+//! ```no_run
+//! use confluence_updater::config::{Page, PageConfig};
+//! use confluence_updater::confluence::{ClientOptions, ConfluenceClient};
+//!
+//! # async fn publish() -> confluence_updater::error::Result<()> {
+//! let client = ConfluenceClient::connect(&ClientOptions {
+//!     fqdn: "https://your-domain.atlassian.net".to_string(),
+//!     user: Some("user@example.com".to_string()),
+//!     secret: "api-token".to_string(),
+//!     ..Default::default()
+//! })
+//! .await?;
+//!
+//! let page_config = PageConfig {
+//!     file_path: "./README.md".to_string(),
+//!     page_id: Some("1234567890".to_string()),
+//!     ..Default::default()
+//! };
+//!
+//! let mut page = Page::try_from_async(page_config, &client, None).await?;
+//! client.update_confluence_page(&mut page).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Rendering doesn't require a connection, [`link_resolver::OfflineLinkResolver`] renders `pid:` links as
+//! Confluence storage format links:
+//!
+//! ```no_run
+//! use confluence_updater::config::PageConfig;
+//! use confluence_updater::link_resolver::OfflineLinkResolver;
+//! use confluence_updater::render_markdown::HtmlPage;
+//!
+//! # async fn render() -> confluence_updater::error::Result<()> {
+//! let page_config = PageConfig {
+//!     file_path: "./README.md".to_string(),
+//!     ..Default::default()
+//! };
+//!
+//! let html_page = HtmlPage::new(&page_config, &OfflineLinkResolver).await?;
+//! println!("{}", html_page.html);
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+
+/// Persists page links between runs.
+pub mod cache;
+/// Loads config files and resolves them to pages and clients.
+pub mod config;
+/// Client for the Confluence REST API.
+pub mod confluence;
+/// The error and result types of the crate.
+pub mod error;
+mod filter;
+mod git;
+mod include;
+/// Generates a config file from existing Markdown files.
+pub mod init;
+mod interpolate;
+/// Resolves `pid:` links to page URLs.
+pub mod link_resolver;
+/// Removes managed pages that are no longer in the config.
+pub mod prune;
+/// Renders Markdown to Confluence storage format.
+pub mod render_markdown;
+/// Reads secrets from values, environment variables, files, stdin or credential helpers.
+pub mod secret;
+mod template;
+/// Checks config files and pages without connecting to Confluence.
+pub mod validate;
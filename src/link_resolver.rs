@@ -0,0 +1,45 @@
+use crate::confluence::ConfluenceClient;
+use crate::error::Result;
+use async_trait::async_trait;
+
+// ###################################################### //
+//                    Page Link Enum                      //
+// ###################################################### //
+
+/// The link a `pid:` link is rendered as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageLink {
+    /// The WebUI url of the page, replacing the url of the markdown link.
+    Url(String),
+    /// A Confluence storage format link to the page id, resolved by Confluence when the page is viewed.
+    ContentId(String),
+}
+
+// ###################################################### //
+//                  Link Resolver Trait                   //
+// ###################################################### //
+
+/// Resolves the page ids of `pid:` links when rendering markdown.
+#[async_trait]
+pub trait LinkResolver: Send + Sync {
+    /// Resolves the link to the page with the id.
+    async fn resolve(&self, page_id: &str) -> Result<PageLink>;
+}
+
+#[async_trait]
+impl LinkResolver for ConfluenceClient {
+    async fn resolve(&self, page_id: &str) -> Result<PageLink> {
+        Ok(PageLink::Url(self.get_page_link(page_id).await?))
+    }
+}
+
+/// Resolves links without connecting to Confluence, so the linked pages are not checked to exist.
+#[derive(Debug, Default)]
+pub struct OfflineLinkResolver;
+
+#[async_trait]
+impl LinkResolver for OfflineLinkResolver {
+    async fn resolve(&self, page_id: &str) -> Result<PageLink> {
+        Ok(PageLink::ContentId(page_id.to_string()))
+    }
+}
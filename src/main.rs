@@ -1,12 +1,11 @@
-mod config;
-mod confluence;
-mod error;
-mod render_markdown;
-use clap::{Parser, ValueEnum};
-use config::Config;
-use confluence::ConfluenceClient;
-use std::sync::OnceLock;
-use tracing::{error, span, Level};
+use clap::{Parser, Subcommand, ValueEnum};
+use confluence_updater::config::{self, Config, ConfigOptions, DefaultInstance};
+use confluence_updater::confluence::{AuthMethod, ConfluenceClient, Flavor, LabelMode};
+use confluence_updater::prune::{self, PruneMode};
+use confluence_updater::secret::SecretSource;
+use confluence_updater::{error, init, validate};
+use derive_more::Debug;
+use tracing::{error, instrument, span, warn, Level};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -17,44 +16,205 @@ use tracing::{error, span, Level};
     arg_required_else_help = true
 )]
 struct CommandArgs {
-    #[arg(short, long, env = "CU_USER", help = "Confluence user to login with", value_parser = validate_no_quotes)]
-    user: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(
+        short,
+        long,
+        env = "CU_USER",
+        help = "Confluence user to login with. Required for basic authentication.",
+        value_parser = validate_no_quotes
+    )]
+    user: Option<String>,
 
     #[arg(
         short,
         long,
         env = "CU_SECRET",
-        help = "The token/secret to use. https://id.atlassian.com/manage-profile/security/api-tokens",
+        help = "The token/secret to use. An API token for basic, a personal access token for bearer or the client secret for OAuth authentication. https://id.atlassian.com/manage-profile/security/api-tokens",
+        group = "secret_source",
+        value_parser = validate_no_quotes
+    )]
+    #[debug("{:?}", secret.as_ref().map(|_| "<redacted>"))]
+    secret: Option<String>,
+
+    #[arg(
+        long,
+        env = "CU_SECRET_FILE",
+        help = "Read the token/secret from a file.",
+        group = "secret_source",
         value_parser = validate_no_quotes
     )]
-    secret: String,
+    secret_file: Option<String>,
+
+    #[arg(
+        long,
+        help = "Read the token/secret from stdin.",
+        group = "secret_source"
+    )]
+    secret_stdin: bool,
+
+    #[arg(
+        long,
+        env = "CU_CREDENTIAL_HELPER",
+        help = "Get the token/secret from a git credential helper command, which is called with the 'get' argument.",
+        group = "secret_source"
+    )]
+    credential_helper: Option<String>,
 
     #[arg(
         long,
         env = "CU_FQDN",
-        help = "The fully qualified domain name of your Atlassian Cloud.",
+        help = "The fully qualified domain name of your Atlassian Cloud or Confluence Data Center. Required by pages without an instance.",
         value_parser = validate_no_quotes
     )]
-    fqdn: String,
+    fqdn: Option<String>,
+
+    #[arg(
+        long,
+        env = "CU_FLAVOR",
+        default_value = "cloud",
+        help = "The Confluence flavor. Data Center uses personal access tokens and the v1 REST API."
+    )]
+    flavor: Flavor,
+
+    #[arg(
+        long,
+        env = "CU_CONTEXT_PATH",
+        help = "The context path Confluence is served from. Defaults to /wiki for Cloud and none for Data Center.",
+        value_parser = validate_no_quotes
+    )]
+    context_path: Option<String>,
+
+    #[arg(
+        long,
+        env = "CU_AUTH",
+        help = "The authentication method. Defaults to basic for Cloud and bearer for Data Center."
+    )]
+    auth: Option<AuthMethod>,
+
+    #[arg(
+        long,
+        env = "CU_OAUTH_CLIENT_ID",
+        help = "The OAuth 2.0 client id. Required for OAuth authentication.",
+        value_parser = validate_no_quotes
+    )]
+    oauth_client_id: Option<String>,
+
+    #[arg(
+        long,
+        env = "CU_OAUTH_REFRESH_TOKEN",
+        help = "An OAuth 2.0 refresh token. If omitted, the client credentials grant is used.",
+        value_parser = validate_no_quotes
+    )]
+    #[debug("{:?}", oauth_refresh_token.as_ref().map(|_| "<redacted>"))]
+    oauth_refresh_token: Option<String>,
+
+    #[arg(
+        long,
+        env = "CU_OAUTH_REFRESH_TOKEN_FILE",
+        help = "A file containing an OAuth 2.0 refresh token. Rotated refresh tokens are written back to the file.",
+        conflicts_with = "oauth_refresh_token"
+    )]
+    oauth_refresh_token_file: Option<String>,
 
     #[arg(
-        short,
         long,
+        env = "CU_CLOUD_ID",
+        help = "The cloud id of your Atlassian Cloud, used for OAuth authentication. Looked up from the FQDN if omitted.",
+        value_parser = validate_no_quotes
+    )]
+    cloud_id: Option<String>,
+
+    #[arg(
+        name = "config-path",
+        short,
+        long = "config-path",
         default_value = "./confluence-updater.yaml",
         env = "CU_CONFIG_PATH",
-        help = "The path to the config file.",
+        help = "The path to a YAML, TOML or JSON config file. Can be used multiple times.",
+        global = true,
         value_parser = validate_no_quotes
     )]
-    config_path: String,
+    config_paths: Vec<String>,
 
     #[arg(
         name = "label",
         short,
         long = "label",
-        help = "Add a label to all updating pages. Can be used multiple times."
+        help = "Add a label to all updating pages. Can be used multiple times.",
+        global = true
     )]
     labels: Vec<String>,
 
+    #[arg(
+        long,
+        env = "CU_LABEL_MODE",
+        help = "How invalid labels are handled: skipped, normalized or failing the run. Overrides the labelMode of the config file. [default: filter]",
+        global = true
+    )]
+    label_mode: Option<LabelMode>,
+
+    #[arg(
+        name = "page",
+        long = "page",
+        help = "Only update the page with this page id. Can be used multiple times."
+    )]
+    page_filters: Vec<String>,
+
+    #[arg(
+        name = "file",
+        long = "file",
+        help = "Only update pages with a file path matching this glob pattern. Can be used multiple times."
+    )]
+    file_filters: Vec<String>,
+
+    #[arg(
+        name = "tag",
+        long = "tag",
+        help = "Only update pages with this tag. Can be used multiple times."
+    )]
+    tag_filters: Vec<String>,
+
+    #[arg(
+        long,
+        env = "CU_CHANGED_SINCE",
+        help = "Only update pages where the markdown file, included files or images changed since this Git ref."
+    )]
+    changed_since: Option<String>,
+
+    #[arg(
+        long,
+        env = "CU_CHANGED_SINCE_FALLBACK",
+        requires = "changed_since",
+        help = "Check all pages using the page SHA if the changed files can't be read from Git, e.g. in a shallow clone."
+    )]
+    changed_since_fallback: bool,
+
+    #[arg(
+        long,
+        env = "CU_PRUNE",
+        conflicts_with_all = ["page", "file", "tag", "changed_since"],
+        help = "Archive, trash or replace with a notice the managed pages in the prune scope that are no longer in the config."
+    )]
+    prune: Option<PruneMode>,
+
+    #[arg(
+        long,
+        env = "CU_CACHE_FILE",
+        help = "Persist the links of pages referenced with pid: in this file between runs.",
+        value_parser = validate_no_quotes
+    )]
+    cache_file: Option<String>,
+
+    #[arg(
+        long,
+        env = "CU_VERSION_MESSAGE",
+        help = "The version message of the updated pages. Defaults to the SHA and subject of the current Git commit."
+    )]
+    version_message: Option<String>,
+
     #[arg(
         long,
         env = "CU_LOG_LEVEL",
@@ -65,6 +225,47 @@ struct CommandArgs {
     log_level: LogLevel,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(
+        about = "Validate the config file and all pages offline, without connecting to Confluence."
+    )]
+    Validate {
+        #[arg(long, help = "Treat warnings as errors.")]
+        strict: bool,
+    },
+
+    #[command(about = "Print the JSON Schema of the config file.")]
+    Schema,
+
+    #[command(about = "Create a config file with a page for every Markdown file in a directory.")]
+    Init {
+        #[arg(
+            long,
+            default_value = ".",
+            help = "The directory to scan for Markdown files."
+        )]
+        dir: String,
+
+        #[arg(
+            short,
+            long,
+            default_value = "./confluence-updater.yaml",
+            help = "The path of the config file to write."
+        )]
+        output: String,
+
+        #[arg(long, help = "Overwrite the config file if it exists.")]
+        force: bool,
+
+        #[arg(
+            long,
+            help = "Look up the page IDs by title in this space. Requires the connection options."
+        )]
+        space: Option<String>,
+    },
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 enum LogLevel {
     Trace,
@@ -88,7 +289,7 @@ impl From<LogLevel> for Level {
 
 fn validate_no_quotes(s: &str) -> Result<String, String> {
     let arg = s.trim();
-    let is_quoted = |s: &str, c: char| (s.starts_with(c) && s.ends_with(c));
+    let is_quoted = |s: &str, c: char| s.starts_with(c) && s.ends_with(c);
 
     if (is_quoted(arg, '"')) || (is_quoted(arg, '\'')) {
         return Err(String::from("Value must not be quoted, remove the quotes."));
@@ -97,18 +298,63 @@ fn validate_no_quotes(s: &str) -> Result<String, String> {
     Ok(arg.to_string())
 }
 
-static FQDN: OnceLock<String> = OnceLock::new();
-static USER: OnceLock<String> = OnceLock::new();
-static SECRET: OnceLock<String> = OnceLock::new();
+impl CommandArgs {
+    fn secret_source(&self) -> Option<SecretSource> {
+        match (&self.secret, &self.secret_file, &self.credential_helper) {
+            (Some(secret), _, _) => Some(SecretSource::Value(secret.to_string())),
+            (None, Some(path), _) => Some(SecretSource::File(path.to_string())),
+            (None, None, Some(command)) => {
+                Some(SecretSource::CredentialHelper(command.to_string()))
+            }
+            (None, None, None) if self.secret_stdin => Some(SecretSource::Stdin),
+            (None, None, None) => None,
+        }
+    }
+
+    // Connection options of the default instance. The secret is only read when the instance is used.
+    fn default_instance(&self) -> DefaultInstance {
+        DefaultInstance {
+            fqdn: self.fqdn.clone(),
+            flavor: self.flavor,
+            context_path: self.context_path.clone(),
+            auth_method: self.auth,
+            user: self.user.clone(),
+            secret_source: self.secret_source(),
+            oauth_client_id: self.oauth_client_id.clone(),
+            oauth_refresh_token: self.oauth_refresh_token.clone(),
+            oauth_refresh_token_file: self.oauth_refresh_token_file.clone(),
+            cloud_id: self.cloud_id.clone(),
+        }
+    }
+
+    fn config_options(self) -> ConfigOptions {
+        ConfigOptions {
+            default_instance: Some(self.default_instance()),
+            config_paths: self.config_paths,
+            labels: self.labels,
+            label_mode: self.label_mode,
+            page_filters: self.page_filters,
+            file_filters: self.file_filters,
+            tag_filters: self.tag_filters,
+            changed_since: self.changed_since,
+            changed_since_fallback: self.changed_since_fallback,
+            prune: self.prune,
+            cache_file: self.cache_file,
+            version_message: self.version_message,
+        }
+    }
+}
+
+// Connects to the command line instance.
+#[instrument(skip_all, err(Display))]
+async fn connect(args: &CommandArgs) -> error::Result<ConfluenceClient> {
+    ConfluenceClient::connect(&args.default_instance().client_options()?).await
+}
 
 #[tokio::main]
 async fn main() {
     let args = CommandArgs::parse();
 
-    FQDN.set(args.fqdn.to_owned()).unwrap();
-    USER.set(args.user.to_owned()).unwrap();
-    SECRET.set(args.secret.to_owned()).unwrap();
-
     let log_level: Level = args.log_level.into();
     tracing_subscriber::fmt()
         .compact()
@@ -116,24 +362,61 @@ async fn main() {
         .with_max_level(log_level)
         .init();
 
-    let config = match Config::try_from_async(args).await {
-        Ok(config) => config,
-        Err(_) => std::process::exit(1),
-    };
+    match args.command {
+        Some(Command::Validate { strict }) => {
+            let valid = validate::run(
+                &args.config_paths,
+                &args.labels,
+                args.label_mode,
+                args.flavor,
+                strict,
+            )
+            .await;
+            std::process::exit(if valid { 0 } else { 1 })
+        }
+        Some(Command::Schema) => match config::json_schema() {
+            Ok(schema) => {
+                println!("{schema}");
+                std::process::exit(0)
+            }
+            Err(error) => {
+                error!(%error);
+                std::process::exit(1)
+            }
+        },
+        Some(Command::Init {
+            ref dir,
+            ref output,
+            force,
+            ref space,
+        }) => {
+            let client = match space {
+                Some(_) => match connect(&args).await {
+                    Ok(client) => Some(client),
+                    Err(_) => std::process::exit(1),
+                },
+                None => None,
+            };
+
+            let result = init::run(dir, output, force, space.as_deref(), client.as_ref()).await;
+            std::process::exit(if result.is_ok() { 0 } else { 1 })
+        }
+        None => (),
+    }
 
-    let Config {
-        fqdn, user, secret, ..
-    } = &config;
+    let prune_mode = args.prune;
+    let cache_file = args.cache_file.clone();
 
-    let client = match ConfluenceClient::new(fqdn, user, secret) {
-        Ok(client) => client,
+    let mut config = match Config::try_from_async(args.config_options()).await {
+        Ok(config) => config,
         Err(_) => std::process::exit(1),
     };
 
-    for page in config.pages.iter() {
+    for page in config.pages.iter_mut() {
         let span = span!(
             Level::INFO,
             "page",
+            instance = page.instance,
             id = page.page_id,
             title = page.title,
             path = page.file_path,
@@ -142,9 +425,24 @@ async fn main() {
 
         let _enter = span.enter();
 
+        let client = &config.clients[&page.instance];
+
         if let Err(error) = client.update_confluence_page(page).await {
             error!(%error);
             std::process::exit(1)
         }
     }
+
+    if let Some(mode) = prune_mode {
+        if prune::run(&config, mode).await.is_err() {
+            std::process::exit(1)
+        }
+    }
+
+    // A cache file that can't be written only affects the next run, so it's not a failure.
+    if let (Some(path), Some(cache)) = (cache_file, config.cache) {
+        if let Err(error) = cache.save(&path, &config.clients) {
+            warn!(%error, path, "could not save the cache file.");
+        }
+    }
 }
@@ -0,0 +1,74 @@
+use crate::config::Config;
+use crate::error::{Error, Result};
+use clap::ValueEnum;
+use std::collections::HashSet;
+use tracing::{info, instrument, span, Level};
+
+const REMOVED_NOTICE: &str = r#"<ac:structured-macro ac:name="note" ac:schema-version="1"><ac:rich-text-body><p>This page was removed from its source and is no longer updated.</p></ac:rich-text-body></ac:structured-macro>"#;
+
+// ###################################################### //
+//                    Prune Mode Enum                     //
+// ###################################################### //
+
+/// What happens to managed pages that are no longer in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PruneMode {
+    /// Archive the pages.
+    Archive,
+    /// Move the pages to the trash.
+    Trash,
+    /// Replace the content of the pages with a notice.
+    Notice,
+}
+
+// ###################################################### //
+//                   Prune Functions                      //
+// ###################################################### //
+
+/// Prunes the pages managed by the tool within the prune scopes, that are no longer in the config.
+/// Managed pages are the pages with the `pa-token/` label of the current user and the owner of the prune scope.
+#[instrument(skip(config), err(Display))]
+pub async fn run(config: &Config, mode: PruneMode) -> Result<()> {
+    for scope in &config.prune_scopes {
+        let instance = scope.instance_name();
+        let client = &config.clients[instance];
+
+        let configured_ids: HashSet<&str> = config
+            .pages
+            .iter()
+            .filter(|x| x.instance == instance)
+            .map(|x| x.page_id.as_str())
+            .collect();
+
+        let owner = scope.owner.as_deref().ok_or(Error::PruneOwnerMissing)?;
+
+        let managed_pages = client
+            .find_managed_pages(&scope.space, scope.parent_id.as_deref(), owner)
+            .await?;
+
+        let removed_pages = managed_pages
+            .iter()
+            .filter(|x| !configured_ids.contains(x.id.as_str()));
+
+        for page in removed_pages {
+            let span = span!(
+                Level::INFO,
+                "page",
+                instance,
+                id = page.id,
+                title = page.title
+            );
+            let _enter = span.enter();
+
+            match mode {
+                PruneMode::Archive => client.archive_page(&page.id).await?,
+                PruneMode::Trash => client.trash_page(&page.id).await?,
+                PruneMode::Notice => client.replace_with_notice(page, REMOVED_NOTICE).await?,
+            }
+
+            info!("page removed from the config, pruned with mode [{mode:?}].");
+        }
+    }
+
+    Ok(())
+}
@@ -1,8 +1,14 @@
-use crate::{config::PageConfig, confluence::ConfluenceClient, error::Result, FQDN, SECRET, USER};
+use crate::{
+    config::{NoticePosition, NoticeStyle, PageConfig},
+    error::Result,
+    include::ExpandedMarkdown,
+    link_resolver::{LinkResolver, PageLink},
+    template,
+};
 use comrak::{
     arena_tree::Node,
-    format_html,
-    nodes::{Ast, NodeValue},
+    format_html, markdown_to_html,
+    nodes::{Ast, AstNode, NodeValue},
     parse_document, Arena, Options,
 };
 use normalize_path::NormalizePath;
@@ -48,23 +54,34 @@ impl std::fmt::Display for Align {
 //                    HTML Page Struct                    //
 // ###################################################### //
 
+/// A Markdown file rendered to Confluence storage format.
 #[derive(Deserialize, Debug)]
 pub struct HtmlPage {
+    /// Paths of the local images, uploaded as attachments of the page.
     pub image_paths: Vec<String>,
+    /// The text of the first heading, used as title if none is configured.
     pub page_header: Option<String>,
+    /// The rendered text of the source notice, if configured.
+    pub source_notice: Option<String>,
+    /// The Markdown after includes and variables are expanded.
+    pub markdown: String,
+    /// The page content in storage format.
     pub html: String,
 }
 
 impl HtmlPage {
+    /// Renders the page, `pid:` links are resolved by the given resolver.
+    /// Nothing is published, but the source notice runs `git` to look up the repository of the file.
     #[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
-    pub async fn new(page_config: &PageConfig) -> Result<HtmlPage> {
-        let md_file = std::fs::read_to_string(&page_config.file_path)?;
-        let arena = Arena::new();
+    pub async fn new(page_config: &PageConfig, resolver: &dyn LinkResolver) -> Result<HtmlPage> {
+        let mut md_file = ExpandedMarkdown::new(&page_config.file_path)?.markdown;
 
-        let mut options = Options::default();
-        options.extension.superscript = true;
-        options.extension.table = true;
+        if let Some(variables) = &page_config.variables {
+            md_file = template::render(&md_file, variables)?;
+        }
 
+        let arena = Arena::new();
+        let options = markdown_options();
         let root_node = parse_document(&arena, &md_file, &options);
 
         let title = get_and_remove_h1_header(root_node);
@@ -72,7 +89,7 @@ impl HtmlPage {
 
         replace_codeblock_with_html(root_node);
         replace_image_node_with_html(root_node);
-        replace_page_link(root_node).await?;
+        replace_page_link(&arena, root_node, resolver, &options).await?;
 
         if let Some(sup) = &page_config.superscript_header {
             let super_string = format!("^{sup}^");
@@ -80,21 +97,102 @@ impl HtmlPage {
             root_node.prepend(super_node);
         }
 
+        let source_notice = match &page_config.source_notice {
+            Some(notice) => {
+                let text = notice.render_text(&page_config.file_path)?;
+                let notice_node = create_notice_node(&arena, &text, notice.style, &options);
+
+                match notice.position {
+                    NoticePosition::Header => root_node.prepend(notice_node),
+                    NoticePosition::Footer => root_node.append(notice_node),
+                }
+
+                Some(text)
+            }
+            None => None,
+        };
+
         let mut html = String::new();
         format_html(root_node, &options, &mut html)?;
 
         Ok(HtmlPage {
             image_paths,
             page_header: title,
+            source_notice,
+            markdown: md_file,
             html,
         })
     }
 }
 
+/// Returns the paths of all files the page is rendered from: the markdown file, included files and images.
+#[instrument(skip_all, ret(level = Level::TRACE), err(Debug, level = Level::DEBUG))]
+pub fn source_paths(page_config: &PageConfig) -> Result<Vec<String>> {
+    let expanded = ExpandedMarkdown::new(&page_config.file_path)?;
+
+    let arena = Arena::new();
+    let root_node = parse_document(&arena, &expanded.markdown, &markdown_options());
+
+    let mut paths = vec![page_config.file_path.to_string()];
+    paths.extend(expanded.included_paths);
+    paths.extend(get_image_paths(root_node, &page_config.file_path));
+
+    Ok(paths)
+}
+
+/// Returns the title from the h1 header on top of the markdown, if any.
+pub fn page_header(markdown: &str) -> Option<String> {
+    let arena = Arena::new();
+    let root_node = parse_document(&arena, markdown, &markdown_options());
+    get_and_remove_h1_header(root_node)
+}
+
+/// Returns the line number and a description of every image that can't be uploaded.
+pub fn find_image_problems(markdown: &str, md_file_path: &str) -> Vec<(usize, String)> {
+    let arena = Arena::new();
+    let root_node = parse_document(&arena, markdown, &markdown_options());
+
+    let check_image = |node: NodeRef<'_>| {
+        let data = node.data();
+        let NodeValue::Image(node_link) = &data.value else {
+            return None;
+        };
+
+        let url = &node_link.url;
+        let line = data.sourcepos.start.line;
+
+        if url.starts_with("https://") || url.starts_with("http://") {
+            return None;
+        }
+
+        if Path::new(url).has_root() {
+            return Some((line, format!("image path is not a relative path [{url}]")));
+        }
+
+        match Path::new(md_file_path)
+            .with_file_name(url)
+            .normalize()
+            .is_file()
+        {
+            true => None,
+            false => Some((line, format!("image path not valid file path [{url}]"))),
+        }
+    };
+
+    root_node.descendants().filter_map(check_image).collect()
+}
+
 // ###################################################### //
 //       Helper functions for processesing markdown       //
 // ###################################################### //
 
+fn markdown_options() -> Options<'static> {
+    let mut options = Options::default();
+    options.extension.superscript = true;
+    options.extension.table = true;
+    options
+}
+
 // Gets all filesystem paths for images for later uploading.
 fn get_image_paths(root_node: NodeRef<'_>, md_file_path: &str) -> Vec<String> {
     let get_image_node_link = |node: NodeRef<'_>| match &node.data().value {
@@ -245,17 +343,48 @@ fn replace_codeblock_with_html(root_node: NodeRef<'_>) {
     }
 }
 
-// If a markdown link title contains a Confluence page id, this replaces it url with the Confluence Cloud WebUI url.
-async fn replace_page_link(root_node: NodeRef<'_>) -> Result<()> {
+// Creates a node containing the source notice as either a superscript line or a Confluence panel macro.
+fn create_notice_node<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    text: &str,
+    style: NoticeStyle,
+    options: &Options,
+) -> NodeRef<'a> {
+    let macro_name = match style {
+        NoticeStyle::Superscript => return parse_document(arena, &format!("^{text}^"), options),
+        NoticeStyle::Note => "note",
+        NoticeStyle::Info => "info",
+    };
+
+    let raw_html_node = format!(
+        r#"
+            <ac:structured-macro ac:name="{}" ac:schema-version="1">
+            <ac:rich-text-body>{}</ac:rich-text-body>
+            </ac:structured-macro>
+        "#,
+        macro_name,
+        markdown_to_html(text, options).trim_end()
+    )
+    .trim()
+    .to_string();
+
+    let notice_node = parse_document(arena, "", options);
+    notice_node.data_mut().value = NodeValue::Raw(raw_html_node);
+    notice_node
+}
+
+// If a markdown link title contains a Confluence page id, this replaces the link with the link returned by the resolver.
+async fn replace_page_link<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root_node: NodeRef<'a>,
+    resolver: &dyn LinkResolver,
+    options: &Options<'_>,
+) -> Result<()> {
     let link_nodes: Vec<_> = root_node
         .descendants()
         .filter(|node| matches!(node.data().value, NodeValue::Link(_)))
         .collect();
 
-    let fqdn = FQDN.get().unwrap().to_string();
-    let user = USER.get().unwrap().to_string();
-    let secret = SECRET.get().unwrap().to_string();
-
     for node in link_nodes {
         // Borrowing node value in its own scope to prevent holding refcell across await point
         let link_title = {
@@ -276,13 +405,47 @@ async fn replace_page_link(root_node: NodeRef<'_>) -> Result<()> {
         let page_id = parts[1];
         debug!(page_id, "found page id match");
 
-        let client = ConfluenceClient::new(&fqdn, &user, &secret)?;
-        let page_url = client.get_page_link(page_id).await?;
-
-        let node_value = &mut node.data_mut().value;
-        if let NodeValue::Link(ref mut link) = node_value {
-            link.url = page_url;
+        match resolver.resolve(page_id).await? {
+            PageLink::Url(page_url) => {
+                let node_value = &mut node.data_mut().value;
+                if let NodeValue::Link(ref mut link) = node_value {
+                    link.url = page_url;
+                }
+            }
+            PageLink::ContentId(content_id) => {
+                replace_with_content_link(arena, node, &content_id, options)
+            }
         }
     }
     Ok(())
 }
+
+// Replaces a link node with a Confluence storage format link, keeping the children of the link as the link body.
+fn replace_with_content_link<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    link_node: NodeRef<'a>,
+    content_id: &str,
+    options: &Options,
+) {
+    let content_id = content_id
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;");
+
+    let raw_node = |raw_html: String| {
+        let node = parse_document(arena, "", options);
+        node.data_mut().value = NodeValue::Raw(raw_html);
+        node
+    };
+
+    link_node.insert_before(raw_node(format!(
+        r#"<ac:link><ri:page ri:content-id="{content_id}" /><ac:link-body>"#
+    )));
+
+    for child in link_node.children().collect::<Vec<_>>() {
+        link_node.insert_before(child);
+    }
+
+    link_node.insert_before(raw_node("</ac:link-body></ac:link>".to_string()));
+    link_node.detach();
+}
@@ -0,0 +1,117 @@
+use crate::error::{Error, Result};
+use derive_more::Debug;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use tracing::{debug, instrument, Level};
+
+// ###################################################### //
+//                   SecretSource Enum                    //
+// ###################################################### //
+
+/// Where a secret is read from.
+#[derive(Debug)]
+pub enum SecretSource {
+    /// The secret itself.
+    Value(#[debug("<redacted>")] String),
+    /// The name of an environment variable.
+    Env(String),
+    /// The path of a file.
+    File(String),
+    /// Read from stdin.
+    Stdin,
+    /// A command following the git credential helper protocol.
+    CredentialHelper(String),
+}
+
+impl SecretSource {
+    /// Reads the secret from its source. The secret itself is never logged.
+    #[instrument(skip_all, fields(source = self.name()), err(Display, level = Level::DEBUG))]
+    pub fn resolve(&self, fqdn: &str, user: Option<&str>) -> Result<String> {
+        let secret = match self {
+            SecretSource::Value(value) => value.to_string(),
+            SecretSource::Env(name) => std::env::var(name)
+                .map_err(|error| Error::SecretUnavailable(format!("[{name}] {error}")))?,
+            SecretSource::File(path) => std::fs::read_to_string(path)
+                .map_err(|error| Error::SecretUnavailable(format!("[{path}] {error}")))?,
+            SecretSource::Stdin => {
+                let mut input = String::new();
+                std::io::stdin().read_to_string(&mut input)?;
+                input
+            }
+            SecretSource::CredentialHelper(command) => credential_helper(command, fqdn, user)?,
+        };
+
+        let secret = secret.trim().to_string();
+
+        match secret.is_empty() {
+            true => Err(Error::SecretUnavailable(format!(
+                "{} is empty",
+                self.name()
+            ))),
+            false => Ok(secret),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            SecretSource::Value(_) => "secret",
+            SecretSource::Env(_) => "environment variable",
+            SecretSource::File(_) => "secret file",
+            SecretSource::Stdin => "stdin",
+            SecretSource::CredentialHelper(_) => "credential helper",
+        }
+    }
+}
+
+// ###################################################### //
+//        Helper functions for credential helpers         //
+// ###################################################### //
+
+// Runs `<command> get` with the git credential protocol and returns the password it outputs.
+// Like git, a command starting with `!` is a shell snippet, a path is run as is and a name runs `git credential-<name>`.
+// https://git-scm.com/docs/gitcredentials#_custom_helpers
+fn credential_helper(helper: &str, fqdn: &str, user: Option<&str>) -> Result<String> {
+    let command = match helper.strip_prefix('!') {
+        Some(snippet) => snippet.to_string(),
+        None if helper.contains('/') => helper.to_string(),
+        None => format!("git credential-{helper}"),
+    };
+
+    let (protocol, host) = fqdn.split_once("://").unwrap_or(("https", fqdn));
+    let host = host.split('/').next().unwrap_or(host);
+
+    let mut input = format!("protocol={protocol}\nhost={host}\n");
+    if let Some(user) = user {
+        input.push_str(&format!("username={user}\n"));
+    }
+    input.push('\n');
+
+    debug!(helper, protocol, host, "running credential helper");
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{command} get"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|error| Error::SecretUnavailable(format!("[{helper}] {error}")))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        return Err(Error::SecretUnavailable(format!(
+            "[{helper}] exited with {}",
+            output.status
+        )));
+    }
+
+    String::from_utf8(output.stdout)?
+        .lines()
+        .find_map(|line| line.strip_prefix("password=").map(|x| x.to_string()))
+        .ok_or_else(|| Error::SecretUnavailable(format!("[{helper}] returned no password")))
+}
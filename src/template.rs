@@ -0,0 +1,178 @@
+use crate::error::{Error, Result};
+use regex::{Captures, Regex};
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+
+// `\{{` is not a placeholder, the backslash is removed by the markdown renderer.
+fn placeholder_regex() -> Regex {
+    Regex::new(r"\\\{\{|\{\{\s*([A-Za-z0-9_.\-]+)\s*\}\}").expect("invalid regex patteren")
+}
+
+// Replaces all `{{ name }}` placeholders with their value. Undefined placeholders results in an error.
+// Placeholders in fenced code blocks and code spans are left untouched.
+pub fn render(template: &str, variables: &HashMap<String, String>) -> Result<String> {
+    let regex = placeholder_regex();
+    let code_ranges = code_ranges(template);
+    let mut undefined = BTreeSet::new();
+
+    let rendered = regex.replace_all(template, |captures: &Captures| {
+        let placeholder = captures.get(0).expect("capture group 0 always exists");
+
+        let Some(name) = captures.get(1).map(|x| x.as_str()) else {
+            return placeholder.as_str().to_string();
+        };
+
+        if is_code(&code_ranges, placeholder.start()) {
+            return placeholder.as_str().to_string();
+        }
+
+        match variables.get(name) {
+            Some(value) => value.to_string(),
+            None => {
+                undefined.insert(name.to_string());
+                placeholder.as_str().to_string()
+            }
+        }
+    });
+
+    if !undefined.is_empty() {
+        let names = undefined.into_iter().collect::<Vec<_>>().join(", ");
+        return Err(Error::UndefinedVariable(names));
+    }
+
+    Ok(rendered.into_owned())
+}
+
+// Returns the line number and name of every placeholder without a value.
+pub fn find_undefined(template: &str, variables: &HashMap<String, String>) -> Vec<(usize, String)> {
+    let regex = placeholder_regex();
+    let code_ranges = code_ranges(template);
+
+    regex
+        .captures_iter(template)
+        .filter_map(|captures| {
+            let start = captures.get(0)?.start();
+            let name = captures.get(1)?.as_str();
+
+            match is_code(&code_ranges, start) || variables.contains_key(name) {
+                true => None,
+                false => Some((line_number(template, start), name.to_string())),
+            }
+        })
+        .collect()
+}
+
+fn line_number(text: &str, offset: usize) -> usize {
+    text[..offset].matches('\n').count() + 1
+}
+
+pub fn is_code(code_ranges: &[Range<usize>], offset: usize) -> bool {
+    code_ranges.iter().any(|x| x.contains(&offset))
+}
+
+// Returns the byte ranges of the fenced code blocks and code spans in the markdown.
+pub fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut fence: Option<(char, usize, usize)> = None;
+    let mut text_start = 0;
+    let mut offset = 0;
+
+    for line in markdown.split_inclusive('\n') {
+        let (fence_char, fence_length, info) = fence_marker(line);
+
+        match fence {
+            // A closing fence uses the same character, is at least as long and has no info string.
+            Some((open_char, open_length, start))
+                if fence_char == Some(open_char)
+                    && fence_length >= open_length
+                    && info.is_empty() =>
+            {
+                ranges.push(start..offset + line.len());
+                text_start = offset + line.len();
+                fence = None;
+            }
+            Some(_) => (),
+            None => {
+                if let Some(fence_char) = fence_char {
+                    ranges.extend(code_span_ranges(&markdown[text_start..offset], text_start));
+                    fence = Some((fence_char, fence_length, offset));
+                }
+            }
+        }
+
+        offset += line.len();
+    }
+
+    // An unclosed fence runs to the end of the document.
+    match fence {
+        Some((_, _, start)) => ranges.push(start..markdown.len()),
+        None => ranges.extend(code_span_ranges(&markdown[text_start..], text_start)),
+    }
+
+    ranges
+}
+
+// Returns the fence character, the fence length and the info string if the line is a code fence.
+fn fence_marker(line: &str) -> (Option<char>, usize, &str) {
+    let trimmed = line.trim_start_matches(' ');
+
+    if line.len() - trimmed.len() > 3 {
+        return (None, 0, "");
+    }
+
+    for fence_char in ['`', '~'] {
+        let length = trimmed.chars().take_while(|x| *x == fence_char).count();
+        let info = trimmed[length..].trim();
+
+        // Backtick fences can't have backticks in the info string.
+        if length >= 3 && !(fence_char == '`' && info.contains('`')) {
+            return (Some(fence_char), length, info);
+        }
+    }
+
+    (None, 0, "")
+}
+
+// A code span starts with a run of backticks and ends with the next run of backticks of the same length.
+fn code_span_ranges(text: &str, base: usize) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let run_length = |start: usize| bytes[start..].iter().take_while(|x| **x == b'`').count();
+
+    let mut ranges = vec![];
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] != b'`' {
+            index += 1;
+            continue;
+        }
+
+        let length = run_length(index);
+        let mut end = index + length;
+        let mut closing = None;
+
+        while end < bytes.len() {
+            if bytes[end] != b'`' {
+                end += 1;
+                continue;
+            }
+
+            let closing_length = run_length(end);
+            if closing_length == length {
+                closing = Some(end + closing_length);
+                break;
+            }
+            end += closing_length;
+        }
+
+        match closing {
+            Some(closing) => {
+                ranges.push(base + index..base + closing);
+                index = closing;
+            }
+            None => index += length,
+        }
+    }
+
+    ranges
+}
@@ -0,0 +1,378 @@
+use crate::config::{ConfigFile, PageConfig, DEFAULT_INSTANCE};
+use crate::confluence::{self, Flavor, LabelMode, LABEL_MAX_LENGTH, LABEL_PATTERN};
+use crate::error::Error;
+use crate::include::ExpandedMarkdown;
+use crate::link_resolver::OfflineLinkResolver;
+use crate::render_markdown::{self, HtmlPage};
+use crate::template;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tracing::{error, info, instrument, span, warn, Instrument, Level};
+
+// ###################################################### //
+//                   Diagnostic Struct                    //
+// ###################################################### //
+
+/// How severe a problem is, warnings only fail validation in strict mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Likely a mistake, but the pages can be published.
+    Warning,
+    /// The pages can't be published.
+    Error,
+}
+
+/// A problem found in a config file or page.
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// How severe the problem is.
+    pub severity: Severity,
+    /// The config or Markdown file with the problem.
+    pub file: String,
+    /// The line of the problem in the file, if known.
+    pub line: Option<usize>,
+    /// Describes the problem.
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(file: &str, line: Option<usize>, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            file: file.to_string(),
+            line,
+            message,
+        }
+    }
+
+    fn warning(file: &str, line: Option<usize>, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            file: file.to_string(),
+            line,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file, line, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+// ###################################################### //
+//                  Validation Functions                  //
+// ###################################################### //
+
+/// Validates the config files and all pages without connecting to Confluence, logging every problem found.
+/// Returns false if any errors were found, or any warnings in strict mode.
+#[instrument(skip_all)]
+pub async fn run(
+    config_paths: &[String],
+    cli_labels: &[String],
+    cli_label_mode: Option<LabelMode>,
+    default_flavor: Flavor,
+    strict: bool,
+) -> bool {
+    let diagnostics = validate(config_paths, cli_labels, cli_label_mode, default_flavor).await;
+
+    for diagnostic in &diagnostics {
+        match diagnostic.severity {
+            Severity::Error => error!("{diagnostic}"),
+            Severity::Warning => warn!("{diagnostic}"),
+        }
+    }
+
+    let count = |severity| {
+        diagnostics
+            .iter()
+            .filter(|x| x.severity == severity)
+            .count()
+    };
+    let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+
+    info!(errors, warnings, strict, "validation finished.");
+
+    errors == 0 && (!strict || warnings == 0)
+}
+
+/// Validates the config files and all pages without connecting to Confluence, returning every problem found.
+pub async fn validate(
+    config_paths: &[String],
+    cli_labels: &[String],
+    cli_label_mode: Option<LabelMode>,
+    default_flavor: Flavor,
+) -> Vec<Diagnostic> {
+    let mut config_files = match ConfigFile::load_all(config_paths) {
+        Ok(config_files) => config_files,
+        Err(Error::InvalidConfigFile { path, source }) => {
+            return vec![Diagnostic::error(&path, None, source.to_string())]
+        }
+        Err(error) => {
+            return vec![Diagnostic::error(
+                &config_paths.join(", "),
+                None,
+                error.to_string(),
+            )]
+        }
+    };
+
+    let instances = match ConfigFile::merge_instances(&mut config_files) {
+        Ok(instances) => instances,
+        Err(error) => {
+            return vec![Diagnostic::error(
+                &config_paths.join(", "),
+                None,
+                error.to_string(),
+            )]
+        }
+    };
+
+    let mut diagnostics = vec![];
+    let mut seen_pages: HashMap<(String, String), usize> = HashMap::new();
+
+    for config_file in config_files.iter_mut() {
+        let config_path = config_file.path.to_string();
+        let config_text = std::fs::read_to_string(&config_path).unwrap_or_default();
+        let mut seen_in_file: HashMap<String, usize> = HashMap::new();
+
+        for mut page_config in config_file.pages.drain(..).collect::<Vec<_>>() {
+            let span = span!(
+                Level::INFO,
+                "page",
+                id = page_config.page_id,
+                path = page_config.file_path
+            );
+            let enter = span.enter();
+
+            let instance = page_config.instance_name().to_string();
+            let page_id = page_config.page_id.clone().unwrap_or_default();
+
+            // Pages looked up by space and title are located in the config file by their file name, since
+            // the file path was made relative to the current directory when the config file was loaded.
+            let file_name = Path::new(&page_config.file_path)
+                .file_name()
+                .map(|x| x.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            let identifier = match page_id.is_empty() {
+                true => ("filePath", file_name),
+                false => ("pageId", page_id.to_string()),
+            };
+
+            let occurrence_in_file = seen_in_file.entry(identifier.1.to_string()).or_default();
+            *occurrence_in_file += 1;
+
+            let page_line = find_line(&config_text, *occurrence_in_file, |line| {
+                line.contains(identifier.0) && line.contains(&identifier.1)
+            });
+
+            if page_id.trim().is_empty() && page_config.space.is_none() {
+                let message = format!("page id or space is missing [{}]", page_config.file_path);
+                diagnostics.push(Diagnostic::error(&config_path, page_line, message));
+            }
+
+            // Looking up pages by their h1 header would create a new page whenever the header changes.
+            if page_id.trim().is_empty()
+                && page_config.space.is_some()
+                && page_config.override_title.is_none()
+            {
+                let message = format!(
+                    "title is required to look up the page by space and title [{}]",
+                    page_config.file_path
+                );
+                diagnostics.push(Diagnostic::error(&config_path, page_line, message));
+            }
+
+            if !page_id.is_empty() {
+                let occurrence = seen_pages
+                    .entry((instance.to_string(), page_id.to_string()))
+                    .or_default();
+                *occurrence += 1;
+
+                if *occurrence == 2 {
+                    let message = format!("duplicate page id [{page_id}]");
+                    diagnostics.push(Diagnostic::error(&config_path, page_line, message));
+                }
+            }
+
+            if instance != DEFAULT_INSTANCE && !instances.contains_key(&instance) {
+                let message = format!("instance is not defined [{instance}]");
+                diagnostics.push(Diagnostic::error(&config_path, page_line, message));
+            }
+
+            config_file.apply_defaults(&mut page_config, cli_labels, cli_label_mode);
+            diagnostics.extend(validate_labels(&page_config, &config_path, &config_text));
+
+            let flavor = match instances.get(&instance) {
+                Some(instance) => instance.flavor.unwrap_or_default(),
+                None => default_flavor,
+            };
+            diagnostics.extend(validate_restrictions(
+                &page_config,
+                flavor,
+                &config_path,
+                &config_text,
+            ));
+
+            let content_diagnostics = validate_content(&page_config, &config_path, page_line);
+            drop(enter);
+
+            // Pages without content errors are rendered offline, to catch the errors only found when rendering.
+            if content_diagnostics
+                .iter()
+                .all(|x| x.severity != Severity::Error)
+            {
+                if let Err(error) = HtmlPage::new(&page_config, &OfflineLinkResolver)
+                    .instrument(span)
+                    .await
+                {
+                    diagnostics.push(Diagnostic::error(
+                        &config_path,
+                        page_line,
+                        error.to_string(),
+                    ));
+                }
+            }
+
+            diagnostics.extend(content_diagnostics);
+        }
+    }
+
+    // Command line labels and shared include files are checked once per page, only report them once.
+    let mut reported = HashSet::new();
+    diagnostics.retain(|x| reported.insert(x.to_string()));
+
+    diagnostics
+}
+
+// Confluence Cloud can't look up users by email, so restriction users must be account ids.
+fn validate_restrictions(
+    page_config: &PageConfig,
+    flavor: Flavor,
+    config_path: &str,
+    config_text: &str,
+) -> Vec<Diagnostic> {
+    let Some(restrictions) = &page_config.restrictions else {
+        return vec![];
+    };
+
+    if flavor != Flavor::Cloud {
+        return vec![];
+    }
+
+    restrictions
+        .view
+        .iter()
+        .chain(restrictions.edit.iter())
+        .flat_map(|x| x.users.iter())
+        .filter(|user| user.contains('@'))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .map(|user| {
+            let line = find_line(config_text, 1, |line| line.contains(user.as_str()));
+            let message = format!("restriction users must be account ids on Confluence Cloud, emails can't be looked up [{user}]");
+            Diagnostic::error(config_path, line, message)
+        })
+        .collect()
+}
+
+fn validate_labels(
+    page_config: &PageConfig,
+    config_path: &str,
+    config_text: &str,
+) -> Vec<Diagnostic> {
+    let labels = page_config.labels.as_deref().unwrap_or_default();
+
+    // Normalized labels are reported as warnings too, since the label in Confluence differs from the config.
+    let to_diagnostic = |label: &String| {
+        let (severity, outcome) = match page_config.label_mode {
+            LabelMode::Filter => (Severity::Warning, "will be skipped".to_string()),
+            LabelMode::Normalize => (
+                Severity::Warning,
+                format!(
+                    "will be normalized to [{}]",
+                    confluence::normalize_label(label)
+                ),
+            ),
+            LabelMode::Strict => (Severity::Error, "fails the run".to_string()),
+        };
+
+        let message = format!("invalid label [{label}] {outcome}. labels must match the following regex: {LABEL_PATTERN} and be at most {LABEL_MAX_LENGTH} characters");
+
+        let (file, line) = match find_line(config_text, 1, |line| line.contains(label.as_str())) {
+            Some(line) => (config_path, Some(line)),
+            None => ("command line", None),
+        };
+
+        Diagnostic {
+            severity,
+            file: file.to_string(),
+            line,
+            message,
+        }
+    };
+
+    labels
+        .iter()
+        .filter(|label| !confluence::is_valid_label(label))
+        .map(to_diagnostic)
+        .collect()
+}
+
+fn validate_content(
+    page_config: &PageConfig,
+    config_path: &str,
+    page_line: Option<usize>,
+) -> Vec<Diagnostic> {
+    let file_path = &page_config.file_path;
+
+    let expanded = match ExpandedMarkdown::new(file_path) {
+        Ok(expanded) => expanded,
+        Err(error) => return vec![Diagnostic::error(config_path, page_line, error.to_string())],
+    };
+
+    let mut diagnostics = vec![];
+
+    if page_config.override_title.is_none()
+        && render_markdown::page_header(&expanded.markdown).is_none()
+    {
+        let message = crate::error::Error::PageHeaderMissing.to_string();
+        diagnostics.push(Diagnostic::error(file_path, Some(1), message));
+    }
+
+    // Each source file is checked on its own, so line numbers and image paths are relative to that file.
+    let source_files = std::iter::once(file_path).chain(expanded.included_paths.iter());
+
+    for source_file in source_files {
+        let Ok(markdown) = std::fs::read_to_string(source_file) else {
+            continue;
+        };
+
+        for (line, message) in render_markdown::find_image_problems(&markdown, source_file) {
+            diagnostics.push(Diagnostic::warning(source_file, Some(line), message));
+        }
+
+        if let Some(variables) = &page_config.variables {
+            for (line, name) in template::find_undefined(&markdown, variables) {
+                let message = format!("undefined template variable [{name}]");
+                diagnostics.push(Diagnostic::error(source_file, Some(line), message));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+// Returns the line number of the nth line matching the predicate.
+fn find_line(text: &str, nth: usize, predicate: impl Fn(&str) -> bool) -> Option<usize> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| predicate(line))
+        .nth(nth.saturating_sub(1))
+        .map(|(index, _)| index + 1)
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::{MockConfluence, Workspace};
+
+const CONFIG: &str = r#"
+pages:
+  - filePath: ./README.md
+    pageId: "100"
+  - filePath: ./architecture.md
+    pageId: "200"
+"#;
+
+fn cached_links(workspace: &Workspace) -> serde_json::Value {
+    let cache: serde_json::Value =
+        serde_json::from_str(&workspace.read("cache.json")).expect("invalid cache file");
+    let (_, links) = cache["links"].as_object().unwrap().iter().next().unwrap();
+    links.clone()
+}
+
+#[tokio::test]
+async fn drops_links_of_changed_pages() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+    server.add_page("200", "Architecture");
+    server.add_page("300", "Onboarding");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write(
+        "README.md",
+        "# Runbook\n\n[architecture](./architecture.md \"pid:200\") and [onboarding](./onboarding.md \"pid:300\")\n",
+    );
+    workspace.write("architecture.md", "# Architecture\n");
+
+    workspace
+        .run_ok(&server, &["--cache-file", "cache.json"])
+        .await;
+
+    // Page 200 was updated in this run, so its link may have changed.
+    let links = cached_links(&workspace);
+    assert!(links.get("200").is_none(), "{links}");
+    assert!(links.get("300").is_some(), "{links}");
+}
+
+#[tokio::test]
+async fn expires_cached_links() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+    server.add_page("300", "Onboarding");
+
+    let workspace = Workspace::new();
+    workspace.write(
+        "confluence-updater.yaml",
+        &CONFIG[..CONFIG.find("  - filePath: ./architecture.md").unwrap()],
+    );
+    workspace.write(
+        "README.md",
+        "# Runbook\n\n[onboarding](./onboarding.md \"pid:300\")\n",
+    );
+    workspace
+        .run_ok(&server, &["--cache-file", "cache.json"])
+        .await;
+
+    // The page is moved to another space in Confluence and the cached link is older than a day.
+    server.state().pages.get_mut("300").unwrap().space_key = "NEW".to_string();
+    let cache = workspace.read("cache.json");
+    let cached_at = cached_links(&workspace)["300"]["cached_at"].to_string();
+    workspace.write("cache.json", &cache.replace(&cached_at, "0"));
+
+    workspace.write(
+        "README.md",
+        "# Runbook\n\nSee [onboarding](./onboarding.md \"pid:300\")\n",
+    );
+    workspace
+        .run_ok(&server, &["--cache-file", "cache.json"])
+        .await;
+
+    let body = server.page("100").body;
+    assert!(body.contains("/spaces/NEW/pages/300"), "{body}");
+}
@@ -0,0 +1,639 @@
+// An in-process mock of the Confluence REST API, implementing the APIv1 and APIv2 endpoints used by the client.
+// Endpoints are served both with the /wiki context path of Confluence Cloud and without, like Data Center.
+#![allow(dead_code)]
+
+use axum::extract::{Multipart, Path, Query, Request, State};
+use axum::http::{Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::process::Output;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tempfile::TempDir;
+
+pub const USER_EMAIL: &str = "test.user@example.com";
+pub const USER_ACCOUNT_ID: &str = "557058:test-user";
+
+// ###################################################### //
+//                     Mock State                         //
+// ###################################################### //
+
+#[derive(Debug, Clone, Default)]
+pub struct MockPage {
+    pub id: String,
+    pub title: String,
+    pub space_key: String,
+    pub version: u64,
+    pub version_message: Option<String>,
+    pub parent_id: Option<String>,
+    pub body: String,
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct MockState {
+    pub base_url: String,
+    pub pages: HashMap<String, MockPage>,
+    pub properties: HashMap<(String, String), (Value, u64)>,
+    pub attachments: Vec<(String, String)>,
+    pub restrictions: HashMap<String, Value>,
+    pub trashed: Vec<String>,
+    pub archived: Vec<String>,
+    pub requests: Vec<RecordedRequest>,
+    next_page_id: u64,
+}
+
+type Shared = Arc<Mutex<MockState>>;
+
+fn lock(state: &Shared) -> MutexGuard<'_, MockState> {
+    state.lock().expect("poisoned mock state")
+}
+
+fn not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "message": "not found" })),
+    )
+        .into_response()
+}
+
+fn bad_request(message: &str) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "message": message }))).into_response()
+}
+
+// Parses a CQL query of `field = value` clauses joined by `and`, with quoted or plain values, the only CQL the
+// client sends. Returns `None` for anything else, which Confluence would reject as well.
+fn parse_cql(cql: &str) -> Option<Vec<(String, String)>> {
+    let clause =
+        regex::Regex::new(r#"^\s*([A-Za-z.]+)\s*=\s*(?:"((?:[^"\\]|\\.)*)"|([\w-]+))\s*$"#)
+            .unwrap();
+
+    cql.split(" and ")
+        .map(|x| {
+            let captures = clause.captures(x)?;
+            let value = match (captures.get(2), captures.get(3)) {
+                (Some(quoted), _) => quoted.as_str().replace("\\\"", "\"").replace("\\\\", "\\"),
+                (None, Some(word)) => word.as_str().to_string(),
+                (None, None) => return None,
+            };
+            Some((captures[1].to_string(), value))
+        })
+        .collect()
+}
+
+fn expands(query: &HashMap<String, String>, name: &str) -> bool {
+    query
+        .get("expand")
+        .is_some_and(|x| x.split(',').any(|x| x == name))
+}
+
+// The ids of all pages above the page, the direct parent first.
+fn ancestor_ids(pages: &HashMap<String, MockPage>, page: &MockPage) -> Vec<String> {
+    let mut ids = vec![];
+    let mut parent_id = page.parent_id.clone();
+
+    while let Some(id) = parent_id {
+        parent_id = pages.get(&id).and_then(|x| x.parent_id.clone());
+        ids.push(id);
+    }
+
+    ids
+}
+
+fn labels_json(labels: &[String]) -> Value {
+    let results: Vec<Value> = labels
+        .iter()
+        .map(|name| json!({ "prefix": "global", "name": name }))
+        .collect();
+    json!({ "results": results })
+}
+
+fn links_json(base_url: &str, page: &MockPage) -> Value {
+    json!({
+        "base": format!("{base_url}/wiki"),
+        "webui": format!("/spaces/{}/pages/{}", page.space_key, page.id),
+    })
+}
+
+// ###################################################### //
+//                     Mock Server                        //
+// ###################################################### //
+
+pub struct MockConfluence {
+    pub url: String,
+    state: Shared,
+}
+
+impl MockConfluence {
+    pub async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("could not bind mock server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let state = Arc::new(Mutex::new(MockState {
+            base_url: url.to_string(),
+            next_page_id: 1000,
+            ..Default::default()
+        }));
+
+        let api = Router::new()
+            .route("/rest/api/user/current", get(current_user))
+            .route("/api/v2/pages/{id}", get(get_page_v2))
+            .route("/rest/api/content", get(find_pages).post(create_page))
+            .route("/rest/api/content/search", get(search_content))
+            .route("/rest/api/content/archive", post(archive_pages))
+            .route(
+                "/rest/api/content/{id}",
+                get(get_content).put(update_page).delete(trash_page),
+            )
+            .route(
+                "/rest/api/content/{id}/child/attachment",
+                put(upload_attachment),
+            )
+            .route(
+                "/rest/api/content/{id}/label",
+                post(add_labels).delete(remove_label),
+            )
+            .route("/rest/api/content/{id}/property", post(create_property))
+            .route(
+                "/rest/api/content/{id}/property/{key}",
+                get(get_property).put(update_property),
+            )
+            .route("/rest/api/content/{id}/restriction", put(set_restriction));
+
+        let app = Router::new()
+            .nest("/wiki", api.clone())
+            .merge(api)
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                record_request,
+            ))
+            .with_state(state.clone());
+
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        Self { url, state }
+    }
+
+    pub fn add_page(&self, id: &str, title: &str) {
+        let page = MockPage {
+            id: id.to_string(),
+            title: title.to_string(),
+            space_key: "TEST".to_string(),
+            version: 1,
+            ..Default::default()
+        };
+        lock(&self.state).pages.insert(id.to_string(), page);
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        lock(&self.state)
+    }
+
+    pub fn page(&self, id: &str) -> MockPage {
+        self.state().pages[id].clone()
+    }
+
+    // Counts the requests with the method and path, ignoring the context path.
+    pub fn count(&self, method: Method, path: &str) -> usize {
+        self.state()
+            .requests
+            .iter()
+            .filter(|x| x.method == method && x.path == path)
+            .count()
+    }
+}
+
+async fn record_request(State(state): State<Shared>, request: Request, next: Next) -> Response {
+    let uri = request.uri();
+    let path = uri.path();
+
+    lock(&state).requests.push(RecordedRequest {
+        method: request.method().clone(),
+        path: path.strip_prefix("/wiki").unwrap_or(path).to_string(),
+        query: uri.query().map(String::from),
+    });
+
+    next.run(request).await
+}
+
+// ###################################################### //
+//                    Mock Handlers                       //
+// ###################################################### //
+
+async fn current_user() -> Json<Value> {
+    Json(json!({ "accountId": USER_ACCOUNT_ID, "email": USER_EMAIL, "displayName": "Test User" }))
+}
+
+async fn get_page_v2(State(state): State<Shared>, Path(id): Path<String>) -> Response {
+    let state = lock(&state);
+    let Some(page) = state.pages.get(&id) else {
+        return not_found();
+    };
+
+    Json(json!({
+        "id": page.id,
+        "title": page.title,
+        "parentId": page.parent_id,
+        "version": { "number": page.version },
+        "labels": labels_json(&page.labels),
+        "_links": links_json(&state.base_url, page),
+    }))
+    .into_response()
+}
+
+async fn get_content(State(state): State<Shared>, Path(id): Path<String>) -> Response {
+    let state = lock(&state);
+    let Some(page) = state.pages.get(&id) else {
+        return not_found();
+    };
+
+    let ancestors: Vec<Value> = page
+        .parent_id
+        .iter()
+        .map(|id| json!({ "id": id }))
+        .collect();
+
+    Json(json!({
+        "id": page.id,
+        "title": page.title,
+        "version": { "number": page.version },
+        "ancestors": ancestors,
+        "metadata": { "labels": labels_json(&page.labels) },
+        "_links": links_json(&state.base_url, page),
+    }))
+    .into_response()
+}
+
+// Like Confluence, every parameter is an optional filter, the type defaults to page and ancestors are only
+// returned when expanded.
+async fn find_pages(
+    State(state): State<Shared>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Json<Value> {
+    let state = lock(&state);
+    let is_page = query.get("type").is_none_or(|x| x == "page");
+
+    let mut pages: Vec<&MockPage> = state
+        .pages
+        .values()
+        .filter(|_| is_page)
+        .filter(|x| query.get("spaceKey").is_none_or(|key| &x.space_key == key))
+        .filter(|x| query.get("title").is_none_or(|title| &x.title == title))
+        .collect();
+    pages.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let results: Vec<Value> = pages
+        .into_iter()
+        .map(|page| {
+            let mut result = json!({ "id": page.id, "title": page.title });
+            if expands(&query, "ancestors") {
+                // Confluence returns the ancestors from the root down to the direct parent.
+                let ancestors: Vec<Value> = ancestor_ids(&state.pages, page)
+                    .into_iter()
+                    .rev()
+                    .map(|id| json!({ "id": id }))
+                    .collect();
+                result["ancestors"] = json!(ancestors);
+            }
+            result
+        })
+        .collect();
+
+    Json(json!({ "results": results }))
+}
+
+// Supports the CQL fields used to find managed pages. Like Confluence, version and labels are only returned
+// when expanded.
+async fn search_content(
+    State(state): State<Shared>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let Some(clauses) = query.get("cql").and_then(|x| parse_cql(x)) else {
+        return bad_request("could not parse cql");
+    };
+
+    let state = lock(&state);
+    let matches = |page: &MockPage| {
+        clauses.iter().all(|(field, value)| match field.as_str() {
+            "type" => value == "page",
+            "space" => &page.space_key == value,
+            "title" => &page.title == value,
+            "label" => page.labels.contains(value),
+            "ancestor" => ancestor_ids(&state.pages, page).contains(value),
+            _ => false,
+        })
+    };
+
+    let mut pages: Vec<&MockPage> = state.pages.values().filter(|x| matches(x)).collect();
+    pages.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let start = query.get("start").and_then(|x| x.parse().ok()).unwrap_or(0);
+    let limit = query
+        .get("limit")
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(25);
+
+    let results: Vec<Value> = pages
+        .into_iter()
+        .skip(start)
+        .take(limit)
+        .map(|page| {
+            let mut result = json!({ "id": page.id, "title": page.title });
+            if expands(&query, "version") {
+                result["version"] = json!({ "number": page.version });
+            }
+            if expands(&query, "metadata.labels") {
+                result["metadata"] = json!({ "labels": labels_json(&page.labels) });
+            }
+            result
+        })
+        .collect();
+
+    Json(json!({ "results": results })).into_response()
+}
+
+async fn trash_page(State(state): State<Shared>, Path(id): Path<String>) -> Response {
+    let mut state = lock(&state);
+    if state.pages.remove(&id).is_none() {
+        return not_found();
+    }
+
+    state.trashed.push(id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn archive_pages(State(state): State<Shared>, Json(body): Json<Value>) -> Json<Value> {
+    let mut state = lock(&state);
+    for page in body["pages"].as_array().into_iter().flatten() {
+        let id = page["id"].as_str().unwrap_or_default().to_string();
+        state.pages.remove(&id);
+        state.archived.push(id);
+    }
+
+    Json(json!({ "id": "archive-task" }))
+}
+
+async fn create_page(State(state): State<Shared>, Json(body): Json<Value>) -> Json<Value> {
+    let mut state = lock(&state);
+    state.next_page_id += 1;
+    let id = state.next_page_id.to_string();
+
+    let page = MockPage {
+        id: id.to_string(),
+        title: body["title"].as_str().unwrap_or_default().to_string(),
+        space_key: body["space"]["key"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        version: 1,
+        parent_id: body["ancestors"][0]["id"].as_str().map(String::from),
+        ..Default::default()
+    };
+    state.pages.insert(id.to_string(), page);
+
+    Json(json!({ "id": id }))
+}
+
+// Like Confluence, labels in the metadata are added to the existing labels of the page.
+async fn update_page(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    Json(body): Json<Value>,
+) -> Response {
+    let mut state = lock(&state);
+    let Some(page) = state.pages.get_mut(&id) else {
+        return not_found();
+    };
+
+    let version = body["version"]["number"].as_u64().unwrap_or_default();
+    if version != page.version + 1 {
+        let message = format!("version must be {}", page.version + 1);
+        return (StatusCode::CONFLICT, Json(json!({ "message": message }))).into_response();
+    }
+
+    page.version = version;
+    page.version_message = body["version"]["message"].as_str().map(String::from);
+    page.title = body["title"].as_str().unwrap_or_default().to_string();
+    page.body = body["body"]["storage"]["value"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+
+    if let Some(parent_id) = body["ancestors"].as_array().and_then(|x| x.last()) {
+        page.parent_id = parent_id["id"].as_str().map(String::from);
+    }
+
+    for label in body["metadata"]["labels"].as_array().into_iter().flatten() {
+        let name = label["name"].as_str().unwrap_or_default().to_string();
+        if !page.labels.contains(&name) {
+            page.labels.push(name);
+        }
+    }
+
+    Json(json!({ "id": page.id, "version": { "number": page.version } })).into_response()
+}
+
+async fn upload_attachment(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    mut multipart: Multipart,
+) -> Json<Value> {
+    while let Some(field) = multipart.next_field().await.unwrap() {
+        if let Some(file_name) = field.file_name() {
+            let attachment = (id.to_string(), file_name.to_string());
+            lock(&state).attachments.push(attachment);
+        }
+    }
+
+    Json(json!({ "results": [] }))
+}
+
+async fn add_labels(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    Json(body): Json<Value>,
+) -> Response {
+    let mut state = lock(&state);
+    let Some(page) = state.pages.get_mut(&id) else {
+        return not_found();
+    };
+
+    for label in body.as_array().into_iter().flatten() {
+        let name = label["name"].as_str().unwrap_or_default().to_string();
+        if !page.labels.contains(&name) {
+            page.labels.push(name);
+        }
+    }
+
+    Json(labels_json(&page.labels)).into_response()
+}
+
+async fn remove_label(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let mut state = lock(&state);
+    let Some(page) = state.pages.get_mut(&id) else {
+        return not_found();
+    };
+
+    page.labels.retain(|x| Some(x) != query.get("name"));
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn get_property(
+    State(state): State<Shared>,
+    Path((id, key)): Path<(String, String)>,
+) -> Response {
+    match lock(&state).properties.get(&(id, key.to_string())) {
+        Some((value, version)) => {
+            Json(json!({ "key": key, "value": value, "version": { "number": version } }))
+                .into_response()
+        }
+        None => not_found(),
+    }
+}
+
+async fn create_property(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    Json(body): Json<Value>,
+) -> Json<Value> {
+    let key = body["key"].as_str().unwrap_or_default().to_string();
+    lock(&state)
+        .properties
+        .insert((id, key), (body["value"].clone(), 1));
+    Json(body)
+}
+
+async fn update_property(
+    State(state): State<Shared>,
+    Path((id, key)): Path<(String, String)>,
+    Json(body): Json<Value>,
+) -> Json<Value> {
+    let version = body["version"]["number"].as_u64().unwrap_or_default();
+    lock(&state)
+        .properties
+        .insert((id, key), (body["value"].clone(), version));
+    Json(body)
+}
+
+async fn set_restriction(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    Json(body): Json<Value>,
+) -> Json<Value> {
+    lock(&state).restrictions.insert(id, body.clone());
+    Json(body)
+}
+
+// ###################################################### //
+//                  Command Line Runner                   //
+// ###################################################### //
+
+// A temporary directory with the config and markdown files, which the binary is run in.
+pub struct Workspace {
+    dir: TempDir,
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        Self {
+            dir: tempfile::tempdir().expect("could not create temporary directory"),
+        }
+    }
+
+    pub fn write(&self, path: &str, contents: &str) {
+        let path = self.dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    pub fn read(&self, path: &str) -> String {
+        std::fs::read_to_string(self.dir.path().join(path)).unwrap()
+    }
+
+    // Runs git in the workspace, without the config of the user running the tests.
+    pub fn git(&self, args: &[&str]) {
+        let output = std::process::Command::new("git")
+            .current_dir(self.dir.path())
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .args([
+                "-c",
+                "user.name=Test User",
+                "-c",
+                "user.email=test.user@example.com",
+            ])
+            .args(args)
+            .output()
+            .expect("could not run git");
+        assert!(
+            output.status.success(),
+            "git {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    // Runs the binary against the mock server as a Confluence Cloud instance, over plain HTTP on a loopback host.
+    pub async fn run(&self, server: &MockConfluence, args: &[&str]) -> Output {
+        self.run_with_env(server, args, &[]).await
+    }
+
+    // Runs the binary like `run`, with the additional environment variables set.
+    pub async fn run_with_env(
+        &self,
+        server: &MockConfluence,
+        args: &[&str],
+        env: &[(&str, &str)],
+    ) -> Output {
+        let mut command = tokio::process::Command::new(env!("CARGO_BIN_EXE_confluence-updater"));
+
+        // Settings of the environment running the tests must not leak into the binary.
+        for (name, _) in std::env::vars().filter(|(name, _)| name.starts_with("CU_")) {
+            command.env_remove(name);
+        }
+
+        command
+            .current_dir(self.dir.path())
+            .args(["--fqdn", &server.url])
+            .args(["--user", USER_EMAIL, "--secret", "api-token"])
+            .args(["--version-message", "Test commit"])
+            .args(args)
+            .envs(env.iter().copied())
+            .output()
+            .await
+            .expect("could not run confluence-updater")
+    }
+
+    pub async fn run_ok(&self, server: &MockConfluence, args: &[&str]) -> String {
+        let output = self.run(server, args).await;
+        let logs = logs(&output);
+        assert!(
+            output.status.success(),
+            "confluence-updater failed:\n{logs}"
+        );
+        logs
+    }
+}
+
+pub fn logs(output: &Output) -> String {
+    format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
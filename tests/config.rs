@@ -0,0 +1,161 @@
+mod common;
+
+use common::{logs, MockConfluence, Workspace};
+
+#[tokio::test]
+async fn resolves_file_paths_relative_to_the_config_file() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Old Title");
+
+    let workspace = Workspace::new();
+    workspace.write(
+        "docs,team/confluence-updater.toml",
+        r#"
+[[pages]]
+filePath = "./README.md"
+pageId = "100"
+"#,
+    );
+    workspace.write("docs,team/README.md", "# Runbook\n");
+
+    workspace
+        .run_ok(&server, &["-c", "docs,team/confluence-updater.toml"])
+        .await;
+
+    assert_eq!(server.page("100").title, "Runbook");
+}
+
+#[tokio::test]
+async fn reports_the_format_of_the_config_file() {
+    let server = MockConfluence::start().await;
+
+    let workspace = Workspace::new();
+    workspace.write(
+        "confluence-updater.json",
+        r#"{ "pages": [{ "filePath": "./README.md", "pageId": "100", "readonly": true }] }"#,
+    );
+
+    let output = workspace
+        .run(&server, &["-c", "confluence-updater.json"])
+        .await;
+    let logs = logs(&output);
+
+    assert!(!output.status.success());
+    assert!(
+        logs.contains("Cound not parse JSON in config file"),
+        "{logs}"
+    );
+    assert!(logs.contains("Did you mean `readOnly`?"), "{logs}");
+}
+
+#[tokio::test]
+async fn accepts_numbers_and_booleans_for_strings_in_every_format() {
+    let configs = [
+        (
+            "confluence-updater.yaml",
+            "variables:\n  replicas: 3\n  ha: true\npages:\n  - filePath: ./README.md\n    pageId: 100\n",
+        ),
+        (
+            "confluence-updater.toml",
+            "[variables]\nreplicas = 3\nha = true\n\n[[pages]]\nfilePath = \"./README.md\"\npageId = 100\n",
+        ),
+        (
+            "confluence-updater.json",
+            r#"{ "variables": { "replicas": 3, "ha": true }, "pages": [{ "filePath": "./README.md", "pageId": 100 }] }"#,
+        ),
+    ];
+
+    for (path, config) in configs {
+        let server = MockConfluence::start().await;
+        server.add_page("100", "Old Title");
+
+        let workspace = Workspace::new();
+        workspace.write(path, config);
+        workspace.write(
+            "README.md",
+            "# Runbook\n\nRun {{ replicas }} replicas, HA is {{ ha }}.\n",
+        );
+
+        workspace.run_ok(&server, &["-c", path, "validate"]).await;
+        workspace.run_ok(&server, &["-c", path]).await;
+
+        let body = server.page("100").body;
+        assert!(
+            body.contains("Run 3 replicas, HA is true."),
+            "{path}: {body}"
+        );
+    }
+}
+
+#[test]
+fn example_config_matches_the_schema() {
+    let schema = confluence_updater::config::json_schema().unwrap();
+    let schema: serde_json::Value = serde_json::from_str(&schema).unwrap();
+    let validator = jsonschema::validator_for(&schema).unwrap();
+
+    let config = std::fs::read_to_string("confluence-updater.yaml").unwrap();
+    let config: serde_json::Value = serde_yml::from_str(&config).unwrap();
+
+    let errors: Vec<String> = validator
+        .iter_errors(&config)
+        .map(|x| x.to_string())
+        .collect();
+    assert!(errors.is_empty(), "{errors:#?}");
+}
+
+#[tokio::test]
+async fn interpolates_environment_variables() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Old Title");
+
+    let workspace = Workspace::new();
+    workspace.write(
+        "confluence-updater.yaml",
+        r#"
+variables:
+  team: ${RUNBOOK_TEAM:-the platform team}
+  price: $$5
+pages:
+  - filePath: ./README.md
+    pageId: ${RUNBOOK_PAGE_ID}
+"#,
+    );
+    workspace.write(
+        "README.md",
+        "# Runbook\n\nMaintained by {{ team }} for {{ price }}.\n",
+    );
+
+    // The page id has no default value, so the run fails without it.
+    let output = workspace.run(&server, &[]).await;
+    assert!(!output.status.success());
+    assert!(
+        logs(&output).contains(
+            "Environment variable(s) not set and without a default value: [RUNBOOK_PAGE_ID]"
+        ),
+        "{}",
+        logs(&output)
+    );
+
+    let output = workspace
+        .run_with_env(&server, &[], &[("RUNBOOK_PAGE_ID", "100")])
+        .await;
+    assert!(output.status.success(), "{}", logs(&output));
+
+    let body = server.page("100").body;
+    assert!(
+        body.contains("Maintained by the platform team for $5."),
+        "{body}"
+    );
+
+    let output = workspace
+        .run_with_env(
+            &server,
+            &[],
+            &[("RUNBOOK_PAGE_ID", "100"), ("RUNBOOK_TEAM", "team A")],
+        )
+        .await;
+    assert!(output.status.success(), "{}", logs(&output));
+
+    let body = server.page("100").body;
+    assert!(body.contains("Maintained by team A for $5."), "{body}");
+}
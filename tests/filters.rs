@@ -0,0 +1,119 @@
+mod common;
+
+use axum::http::Method;
+use common::{logs, MockConfluence, Workspace};
+
+const CONFIG: &str = r#"
+pages:
+  - filePath: ./README.md
+    pageId: "100"
+    tags: [runbook]
+  - filePath: ./docs/guide.md
+    pageId: "200"
+    tags: [guide]
+  - filePath: ./docs/faq.md
+    pageId: "300"
+    tags: [guide]
+"#;
+
+fn workspace() -> Workspace {
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write("README.md", "# Runbook\n\nSome text.\n");
+    workspace.write("docs/guide.md", "# Guide\n\nSome text.\n");
+    workspace.write("docs/faq.md", "# FAQ\n\nSome text.\n");
+
+    workspace
+}
+
+fn updates(server: &MockConfluence) -> [usize; 3] {
+    ["100", "200", "300"].map(|id| server.count(Method::PUT, &format!("/rest/api/content/{id}")))
+}
+
+#[tokio::test]
+async fn updates_only_pages_matching_the_filters() {
+    let server = MockConfluence::start().await;
+    let workspace = workspace();
+    server.add_page("100", "Runbook");
+    server.add_page("200", "Guide");
+    server.add_page("300", "FAQ");
+
+    workspace.run_ok(&server, &["--page", "100"]).await;
+    assert_eq!(updates(&server), [1, 0, 0]);
+
+    workspace.run_ok(&server, &["--file", "docs/f*.md"]).await;
+    assert_eq!(updates(&server), [1, 0, 1]);
+
+    // Every kind of filter must match, so the runbook is excluded by the tag.
+    workspace
+        .run_ok(
+            &server,
+            &["--tag", "guide", "--page", "100", "--page", "200"],
+        )
+        .await;
+    assert_eq!(updates(&server), [1, 1, 1]);
+}
+
+#[tokio::test]
+async fn fails_on_filters_matching_no_page() {
+    let server = MockConfluence::start().await;
+    let workspace = workspace();
+
+    for (args, message) in [
+        (
+            ["--page", "999"],
+            "No pages match the filter: [page id 999]",
+        ),
+        (
+            ["--file", "*.txt"],
+            "No pages match the filter: [file *.txt]",
+        ),
+        (
+            ["--tag", "unknown"],
+            "No pages match the filter: [tag unknown]",
+        ),
+    ] {
+        let output = workspace.run(&server, &args).await;
+        let logs = logs(&output);
+        assert!(!output.status.success(), "{logs}");
+        assert!(logs.contains(message), "{logs}");
+    }
+
+    assert_eq!(updates(&server), [0, 0, 0]);
+}
+
+#[tokio::test]
+async fn updates_only_pages_changed_since_the_git_ref() {
+    let server = MockConfluence::start().await;
+    let workspace = workspace();
+    server.add_page("100", "Runbook");
+    server.add_page("200", "Guide");
+    server.add_page("300", "FAQ");
+
+    workspace.git(&["init", "--quiet"]);
+    workspace.git(&["add", "."]);
+    workspace.git(&["commit", "--quiet", "-m", "Add docs"]);
+
+    let logs = workspace
+        .run_ok(&server, &["--changed-since", "HEAD"])
+        .await;
+    assert!(logs.contains("no changes since HEAD, skipping."), "{logs}");
+    assert_eq!(updates(&server), [0, 0, 0]);
+
+    workspace.write("docs/guide.md", "# Guide\n\nOther text.\n");
+    workspace
+        .run_ok(&server, &["--changed-since", "HEAD"])
+        .await;
+    assert_eq!(updates(&server), [0, 1, 0]);
+
+    // Any page property might have changed with the config file, so all pages are checked.
+    workspace.write("confluence-updater.yaml", &format!("{CONFIG}\n"));
+    let logs = workspace
+        .run_ok(&server, &["--changed-since", "HEAD"])
+        .await;
+    assert!(
+        logs.contains("config file changed, checking all pages."),
+        "{logs}"
+    );
+    assert_eq!(updates(&server), [1, 1, 1]);
+}
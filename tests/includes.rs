@@ -0,0 +1,83 @@
+mod common;
+
+use common::{MockConfluence, Workspace};
+
+const CONFIG: &str = r#"
+pages:
+  - filePath: ./docs/README.md
+    pageId: "100"
+"#;
+
+#[tokio::test]
+async fn rebases_images_of_included_files() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write(
+        "docs/README.md",
+        "# Runbook\n\n<!-- include: ../shared/contacts.md -->\n",
+    );
+    workspace.write(
+        "shared/contacts.md",
+        "![Team][team]\n\n![Logo](./images/logo.png)\n\n[team]: ./images/team.png\n",
+    );
+    workspace.write("shared/images/team.png", "not really a png");
+    workspace.write("shared/images/logo.png", "not really a png");
+
+    workspace.run_ok(&server, &[]).await;
+
+    let mut attachments = server.state().attachments.clone();
+    attachments.sort();
+    assert_eq!(
+        attachments,
+        vec![
+            ("100".to_string(), "logo.png".to_string()),
+            ("100".to_string(), "team.png".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn keeps_include_directives_in_code_blocks() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write(
+        "docs/README.md",
+        "# Runbook\n\n```md\n<!-- include: ./missing.md -->\n```\n",
+    );
+
+    workspace.run_ok(&server, &["validate"]).await;
+    workspace.run_ok(&server, &[]).await;
+
+    let body = server.page("100").body;
+    assert!(
+        body.contains("<![CDATA[<!-- include: ./missing.md -->]]>"),
+        "{body}"
+    );
+}
+
+#[tokio::test]
+async fn names_the_including_file_of_a_missing_include() {
+    let server = MockConfluence::start().await;
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write(
+        "docs/README.md",
+        "# Runbook\n\n<!-- include: ./missing.md -->\n",
+    );
+
+    let output = workspace.run(&server, &["validate"]).await;
+    let logs = common::logs(&output);
+
+    assert!(!output.status.success());
+    assert!(
+        logs.contains("Included file not found: [docs/missing.md], included from [docs/README.md]"),
+        "{logs}"
+    );
+}
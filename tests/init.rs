@@ -0,0 +1,49 @@
+mod common;
+
+use common::{MockConfluence, Workspace};
+
+#[tokio::test]
+async fn scaffolds_pages_relative_to_the_output_file() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let workspace = Workspace::new();
+    workspace.write(
+        "docs/README.md",
+        "# Runbook\n\n<!-- include: ./shared/contacts.md -->\n",
+    );
+    workspace.write("docs/shared/contacts.md", "## Contacts\n");
+    workspace.write("docs/onboarding.md", "# Onboarding\n");
+
+    let args = [
+        "init",
+        "--dir",
+        "./docs",
+        "--space",
+        "TEST",
+        "--output",
+        "config/confluence-updater.yaml",
+    ];
+    workspace.run_ok(&server, &args).await;
+
+    let config = workspace.read("config/confluence-updater.yaml");
+    assert!(
+        config.contains("- filePath: ../docs/README.md\n    pageId: \"100\"\n"),
+        "{config}"
+    );
+    assert!(
+        config.contains(
+            "- filePath: ../docs/onboarding.md\n    space: TEST\n    title: Onboarding\n"
+        ),
+        "{config}"
+    );
+    assert!(!config.contains("contacts.md"), "{config}");
+    assert!(!config.contains("pageId: \"\""), "{config}");
+
+    workspace
+        .run_ok(
+            &server,
+            &["validate", "-c", "config/confluence-updater.yaml"],
+        )
+        .await;
+}
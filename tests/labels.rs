@@ -0,0 +1,73 @@
+mod common;
+
+use axum::http::Method;
+use common::{logs, MockConfluence, Workspace};
+
+fn config(label_mode: &str) -> String {
+    format!(
+        r#"
+labelMode: {label_mode}
+pages:
+  - filePath: ./README.md
+    pageId: "100"
+    labels: [docs, Team Platform, "Runbook#1"]
+"#
+    )
+}
+
+fn workspace(label_mode: &str) -> Workspace {
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", &config(label_mode));
+    workspace.write("README.md", "# Runbook\n\nSome text.\n");
+    workspace
+}
+
+#[tokio::test]
+async fn skips_invalid_labels_in_filter_mode() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let logs = workspace("filter").run_ok(&server, &[]).await;
+    assert!(logs.contains("invalid label [Team Platform]"), "{logs}");
+    assert!(logs.contains("invalid label [Runbook#1]"), "{logs}");
+
+    let labels = server.page("100").labels;
+    assert!(labels.contains(&"docs".to_string()), "{labels:?}");
+    assert!(!labels.iter().any(|x| x.contains("platform")), "{labels:?}");
+    assert!(!labels.iter().any(|x| x.contains("runbook")), "{labels:?}");
+}
+
+#[tokio::test]
+async fn normalizes_invalid_labels_in_normalize_mode() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    workspace("normalize").run_ok(&server, &[]).await;
+
+    let labels = server.page("100").labels;
+    for label in ["docs", "team-platform", "runbook-1"] {
+        assert!(labels.contains(&label.to_string()), "{labels:?}");
+    }
+}
+
+#[tokio::test]
+async fn fails_on_invalid_labels_in_strict_mode() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let workspace = workspace("strict");
+    let output = workspace.run(&server, &[]).await;
+    let logs = logs(&output);
+    assert!(!output.status.success(), "{logs}");
+    assert!(logs.contains("[Team Platform, Runbook#1]"), "{logs}");
+    assert_eq!(server.count(Method::PUT, "/rest/api/content/100"), 0);
+
+    // The label mode given on the command line overrides the config.
+    workspace
+        .run_ok(&server, &["--label-mode", "normalize"])
+        .await;
+    assert!(server
+        .page("100")
+        .labels
+        .contains(&"team-platform".to_string()));
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use axum::http::Method;
+use common::{MockConfluence, Workspace};
+
+const CONFIG: &str = r#"
+pages:
+  - filePath: ./README.md
+    pageId: "100"
+    parentId: "200"
+"#;
+
+#[tokio::test]
+async fn moves_page_to_the_configured_parent() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+    server.add_page("200", "Operations");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write("README.md", "# Runbook\n");
+
+    let logs = workspace.run_ok(&server, &[]).await;
+
+    assert_eq!(server.page("100").parent_id.as_deref(), Some("200"));
+    assert!(
+        logs.contains("moved page from parent [none] to [200]."),
+        "{logs}"
+    );
+}
+
+#[tokio::test]
+async fn moves_unchanged_page_under_the_wrong_parent() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+    server.add_page("200", "Operations");
+    server.add_page("300", "Archive");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write("README.md", "# Runbook\n");
+
+    workspace.run_ok(&server, &[]).await;
+    let logs = workspace.run_ok(&server, &[]).await;
+    assert!(logs.contains("no changes to page, skipping."), "{logs}");
+
+    // The page is moved in Confluence, the SHA is unchanged but the page must be moved back.
+    server.state().pages.get_mut("100").unwrap().parent_id = Some("300".to_string());
+    let logs = workspace.run_ok(&server, &[]).await;
+
+    let page = server.page("100");
+    assert_eq!(page.parent_id.as_deref(), Some("200"));
+    assert_eq!(page.version, 3);
+    assert_eq!(server.count(Method::PUT, "/rest/api/content/100"), 2);
+    assert!(
+        logs.contains("moved page from parent [300] to [200]."),
+        "{logs}"
+    );
+}
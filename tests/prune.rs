@@ -0,0 +1,130 @@
+mod common;
+
+use common::{logs, MockConfluence, Workspace};
+use serde_json::json;
+
+const OWNER_PROPERTY_KEY: &str = "confluence-updater-owner";
+
+const CONFIG: &str = r#"
+owner: team-docs
+prune:
+  space: TEST
+pages:
+  - filePath: ./README.md
+    pageId: "100"
+"#;
+
+// Adds a page that was published by the test user, with the owner property if given.
+fn add_published_page(server: &MockConfluence, id: &str, owner: Option<&str>) {
+    server.add_page(id, &format!("Page {id}"));
+
+    let mut state = server.state();
+    let page = state.pages.get_mut(id).unwrap();
+    page.labels.push("pa-token/test-user".to_string());
+
+    if let Some(owner) = owner {
+        let key = (id.to_string(), OWNER_PROPERTY_KEY.to_string());
+        state.properties.insert(key, (json!({ "owner": owner }), 1));
+    }
+}
+
+#[tokio::test]
+async fn prunes_only_pages_of_the_same_owner() {
+    let server = MockConfluence::start().await;
+    add_published_page(&server, "100", Some("team-docs"));
+    add_published_page(&server, "200", Some("team-docs"));
+    add_published_page(&server, "300", Some("other-repo"));
+    add_published_page(&server, "400", None);
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write("README.md", "# Runbook\n");
+
+    workspace.run_ok(&server, &["--prune", "trash"]).await;
+
+    let state = server.state();
+    assert_eq!(state.trashed, vec!["200".to_string()]);
+    assert!(state.pages.contains_key("100"));
+    assert!(state.pages.contains_key("300"));
+    assert!(state.pages.contains_key("400"));
+}
+
+#[tokio::test]
+async fn marks_updated_pages_with_the_owner() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write("README.md", "# Runbook\n");
+
+    workspace.run_ok(&server, &[]).await;
+
+    let key = ("100".to_string(), OWNER_PROPERTY_KEY.to_string());
+    let (value, _) = server.state().properties[&key].clone();
+    assert_eq!(value, json!({ "owner": "team-docs" }));
+}
+
+#[tokio::test]
+async fn fails_to_prune_without_an_owner() {
+    let server = MockConfluence::start().await;
+    add_published_page(&server, "100", Some("team-docs"));
+    add_published_page(&server, "200", Some("team-docs"));
+
+    let workspace = Workspace::new();
+    workspace.write(
+        "confluence-updater.yaml",
+        &CONFIG.replace("owner: team-docs\n", ""),
+    );
+    workspace.write("README.md", "# Runbook\n");
+
+    let output = workspace.run(&server, &["--prune", "trash"]).await;
+    let logs = logs(&output);
+
+    assert!(!output.status.success(), "{logs}");
+    assert!(logs.contains("Pruning requires an owner"), "{logs}");
+    assert!(server.state().trashed.is_empty(), "{logs}");
+}
+
+#[tokio::test]
+async fn prunes_only_pages_of_the_same_config_file_in_a_repository() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Team A");
+    server.add_page("200", "Team B");
+    server.add_page("300", "Team A Old");
+
+    let workspace = Workspace::new();
+    workspace.git(&["init", "--quiet"]);
+    workspace.git(&["remote", "add", "origin", "git@github.com:org/docs.git"]);
+
+    let team_a = "prune:\n  space: TEST\npages:\n  - filePath: ./a.md\n    pageId: \"100\"\n";
+    let team_b = "prune:\n  space: TEST\npages:\n  - filePath: ./b.md\n    pageId: \"200\"\n";
+    workspace.write(
+        "team-a.yaml",
+        &format!("{team_a}  - filePath: ./old.md\n    pageId: \"300\"\n"),
+    );
+    workspace.write("team-b.yaml", team_b);
+    workspace.write("a.md", "# Team A\n");
+    workspace.write("b.md", "# Team B\n");
+    workspace.write("old.md", "# Team A Old\n");
+
+    workspace.run_ok(&server, &["-c", "team-a.yaml"]).await;
+    workspace.run_ok(&server, &["-c", "team-b.yaml"]).await;
+
+    let key = ("100".to_string(), OWNER_PROPERTY_KEY.to_string());
+    let (value, _) = server.state().properties[&key].clone();
+    assert_eq!(
+        value,
+        json!({ "owner": "https://github.com/org/docs#team-a.yaml" })
+    );
+
+    // Team A removes a page, pruning must not touch the pages of team B in the same space and repository.
+    workspace.write("team-a.yaml", team_a);
+    workspace
+        .run_ok(&server, &["-c", "team-a.yaml", "--prune", "trash"])
+        .await;
+
+    let state = server.state();
+    assert_eq!(state.trashed, vec!["300".to_string()]);
+    assert!(state.pages.contains_key("200"));
+}
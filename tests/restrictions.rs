@@ -0,0 +1,123 @@
+mod common;
+
+use common::{MockConfluence, Workspace, USER_ACCOUNT_ID};
+use serde_json::Value;
+
+fn config(page_properties: &str) -> String {
+    format!(
+        r#"
+pages:
+  - filePath: ./README.md
+    pageId: "100"
+{page_properties}
+"#
+    )
+}
+
+// Returns the restriction of the operation, with users as account ids and groups as names.
+fn restriction(body: &Value, operation: &str) -> (Vec<String>, Vec<String>) {
+    let result = body["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|x| x["operation"] == operation)
+        .unwrap_or_else(|| panic!("no {operation} restriction in {body}"));
+
+    let names = |kind: &str, field: &str| -> Vec<String> {
+        result["restrictions"][kind]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|x| x[field].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    (names("user", "accountId"), names("group", "name"))
+}
+
+async fn run_with(page_properties: &str) -> Option<Value> {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", &config(page_properties));
+    workspace.write("README.md", "# Runbook\n");
+    workspace.run_ok(&server, &[]).await;
+
+    let restriction = server.state().restrictions.get("100").cloned();
+    restriction
+}
+
+#[tokio::test]
+async fn sets_read_only() {
+    let body = run_with("    readOnly: true").await.unwrap();
+
+    assert_eq!(body["results"].as_array().unwrap().len(), 1);
+    let (users, groups) = restriction(&body, "update");
+    assert_eq!(users, vec![USER_ACCOUNT_ID]);
+    assert!(groups.is_empty());
+}
+
+#[tokio::test]
+async fn removes_restrictions_when_not_read_only() {
+    let body = run_with("    readOnly: false").await.unwrap();
+
+    assert_eq!(body["results"].as_array().unwrap().len(), 1);
+    let (users, groups) = restriction(&body, "update");
+    assert!(users.is_empty() && groups.is_empty(), "{body}");
+}
+
+#[tokio::test]
+async fn leaves_restrictions_unset_by_default() {
+    assert_eq!(run_with("").await, None);
+}
+
+#[tokio::test]
+async fn sets_view_and_edit_restrictions() {
+    let body = run_with(
+        r#"    restrictions:
+      view:
+        groups: [readers]
+      edit:
+        users: ["557058:jane", "557058:john"]
+        groups: [writers]"#,
+    )
+    .await
+    .unwrap();
+
+    let (users, groups) = restriction(&body, "read");
+    assert_eq!(users, vec![USER_ACCOUNT_ID, "557058:jane", "557058:john"]);
+    assert_eq!(groups, vec!["readers", "writers"]);
+
+    let (users, groups) = restriction(&body, "update");
+    assert_eq!(users, vec![USER_ACCOUNT_ID, "557058:jane", "557058:john"]);
+    assert_eq!(groups, vec!["writers"]);
+}
+
+#[tokio::test]
+async fn rejects_email_users() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let workspace = Workspace::new();
+    let page_properties = "    restrictions:\n      edit:\n        users: [jane@example.com]";
+    workspace.write("confluence-updater.yaml", &config(page_properties));
+    workspace.write("README.md", "# Runbook\n");
+
+    let output = workspace.run(&server, &["validate"]).await;
+    let logs = common::logs(&output);
+    assert!(!output.status.success());
+    assert!(
+        logs.contains("emails can't be looked up [jane@example.com]"),
+        "{logs}"
+    );
+
+    let output = workspace.run(&server, &[]).await;
+    let logs = common::logs(&output);
+    assert!(!output.status.success());
+    assert!(
+        logs.contains("emails can't be looked up: [jane@example.com]"),
+        "{logs}"
+    );
+    assert_eq!(server.state().restrictions.get("100"), None);
+}
@@ -0,0 +1,324 @@
+mod common;
+
+use axum::http::Method;
+use common::{MockConfluence, Workspace};
+
+const CONFIG: &str = r#"
+pages:
+  - filePath: ./README.md
+    pageId: "100"
+    labels:
+      - docs
+"#;
+
+#[tokio::test]
+async fn updates_page_content_and_labels() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Old Title");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write("README.md", "# Runbook\n\nSome **bold** text.\n");
+
+    workspace.run_ok(&server, &[]).await;
+
+    let page = server.page("100");
+    assert_eq!(page.title, "Runbook");
+    assert_eq!(page.version, 2);
+    assert_eq!(page.version_message.as_deref(), Some("Test commit"));
+    assert!(page.body.contains("<strong>bold</strong>"), "{}", page.body);
+    assert!(!page.body.contains("<h1>"), "{}", page.body);
+
+    assert!(page.labels.contains(&"docs".to_string()));
+    assert!(page.labels.contains(&"pa-token/test-user".to_string()));
+    assert!(page.labels.iter().any(|x| x.starts_with("page-sha/")));
+
+    let property_key = ("100".to_string(), "confluence-updater-labels".to_string());
+    let (value, _) = server.state().properties[&property_key].clone();
+    assert_eq!(value["labels"], serde_json::json!(["docs"]));
+}
+
+#[tokio::test]
+async fn skips_unchanged_page() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write("README.md", "# Runbook\n\nSome text.\n");
+
+    workspace.run_ok(&server, &[]).await;
+    let logs = workspace.run_ok(&server, &[]).await;
+
+    assert!(logs.contains("no changes to page, skipping."), "{logs}");
+    assert_eq!(server.count(Method::PUT, "/rest/api/content/100"), 1);
+    assert_eq!(server.page("100").version, 2);
+
+    // Changing the markdown changes the SHA, so the page is updated again.
+    workspace.write("README.md", "# Runbook\n\nOther text.\n");
+    workspace.run_ok(&server, &[]).await;
+
+    assert_eq!(server.count(Method::PUT, "/rest/api/content/100"), 2);
+    assert_eq!(server.page("100").version, 3);
+}
+
+#[tokio::test]
+async fn fetches_current_user_once() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "First");
+    server.add_page("200", "Second");
+
+    let workspace = Workspace::new();
+    workspace.write(
+        "confluence-updater.yaml",
+        r#"
+readOnly: true
+pages:
+  - filePath: ./first.md
+    pageId: "100"
+  - filePath: ./second.md
+    pageId: "200"
+"#,
+    );
+    workspace.write("first.md", "# First\n");
+    workspace.write("second.md", "# Second\n");
+
+    workspace.run_ok(&server, &[]).await;
+
+    assert_eq!(server.count(Method::GET, "/rest/api/user/current"), 1);
+}
+
+#[tokio::test]
+async fn removes_stale_labels_and_keeps_others() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+    server
+        .state()
+        .pages
+        .get_mut("100")
+        .unwrap()
+        .labels
+        .push("added-by-hand".to_string());
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write("README.md", "# Runbook\n");
+    workspace.run_ok(&server, &[]).await;
+
+    let old_sha = server
+        .page("100")
+        .labels
+        .into_iter()
+        .find(|x| x.starts_with("page-sha/"))
+        .unwrap();
+
+    workspace.write(
+        "confluence-updater.yaml",
+        &CONFIG.replace("- docs", "- runbooks"),
+    );
+    workspace.run_ok(&server, &[]).await;
+
+    let labels = server.page("100").labels;
+    assert!(labels.contains(&"runbooks".to_string()), "{labels:?}");
+    assert!(labels.contains(&"added-by-hand".to_string()), "{labels:?}");
+    assert!(!labels.contains(&"docs".to_string()), "{labels:?}");
+    assert!(!labels.contains(&old_sha), "{labels:?}");
+}
+
+#[tokio::test]
+async fn uploads_image_attachments() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write(
+        "README.md",
+        "# Runbook\n\n![Diagram](./images/diagram.png)\n",
+    );
+    workspace.write("images/diagram.png", "not really a png");
+
+    workspace.run_ok(&server, &[]).await;
+
+    let attachments = server.state().attachments.clone();
+    assert_eq!(
+        attachments,
+        vec![("100".to_string(), "diagram.png".to_string())]
+    );
+    assert!(server.page("100").body.contains("diagram.png"));
+}
+
+#[tokio::test]
+async fn replaces_page_id_links() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+    server.add_page("200", "Architecture");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write(
+        "README.md",
+        "# Runbook\n\nSee [architecture](./architecture.md \"pid:200\").\n",
+    );
+
+    workspace.run_ok(&server, &[]).await;
+
+    let expected = format!("{}/wiki/spaces/TEST/pages/200", server.url);
+    assert!(
+        server.page("100").body.contains(&expected),
+        "{}",
+        server.page("100").body
+    );
+}
+
+#[tokio::test]
+async fn creates_missing_page_by_space_and_title() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Team");
+
+    let workspace = Workspace::new();
+    workspace.write(
+        "confluence-updater.yaml",
+        r#"
+pages:
+  - filePath: ./README.md
+    space: TEST
+    parent: Team
+    title: Onboarding
+    createMissing: true
+"#,
+    );
+    workspace.write("README.md", "# Onboarding\n");
+
+    workspace.run_ok(&server, &[]).await;
+
+    let state = server.state();
+    let page = state
+        .pages
+        .values()
+        .find(|x| x.title == "Onboarding")
+        .expect("page was not created");
+
+    assert_eq!(page.parent_id.as_deref(), Some("100"));
+    assert_eq!(page.version, 2);
+}
+
+#[tokio::test]
+async fn updates_data_center_page() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write("README.md", "# Runbook\n");
+
+    workspace
+        .run_ok(&server, &["--flavor", "datacenter", "--auth", "basic"])
+        .await;
+
+    assert_eq!(server.count(Method::GET, "/rest/api/content/100"), 1);
+    assert_eq!(server.count(Method::GET, "/api/v2/pages/100"), 0);
+    assert_eq!(server.page("100").version, 2);
+}
+
+#[tokio::test]
+async fn fails_on_missing_page() {
+    let server = MockConfluence::start().await;
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write("README.md", "# Runbook\n");
+
+    let output = workspace.run(&server, &[]).await;
+
+    assert!(!output.status.success());
+    assert!(server.state().restrictions.is_empty());
+    assert_eq!(server.count(Method::PUT, "/rest/api/content/100"), 0);
+}
+
+#[tokio::test]
+async fn creates_missing_pages_only_when_updating() {
+    let server = MockConfluence::start().await;
+
+    let workspace = Workspace::new();
+    workspace.write(
+        "confluence-updater.yaml",
+        r#"
+pages:
+  - filePath: ./README.md
+    space: TEST
+    title: Onboarding
+    createMissing: true
+  - filePath: ./missing.md
+    pageId: "200"
+"#,
+    );
+    workspace.write("README.md", "# Onboarding\n");
+
+    let output = workspace.run(&server, &[]).await;
+
+    assert!(!output.status.success());
+    assert!(server.state().pages.is_empty());
+}
+
+#[tokio::test]
+async fn requires_a_title_to_look_up_pages_by_space() {
+    let server = MockConfluence::start().await;
+
+    let workspace = Workspace::new();
+    workspace.write(
+        "confluence-updater.yaml",
+        r#"
+pages:
+  - filePath: ./README.md
+    space: TEST
+    createMissing: true
+"#,
+    );
+    workspace.write("README.md", "# Onboarding\n");
+
+    let output = workspace.run(&server, &[]).await;
+    let logs = common::logs(&output);
+
+    assert!(!output.status.success());
+    assert!(logs.contains("A title is required"), "{logs}");
+    assert!(server.state().pages.is_empty());
+}
+
+#[tokio::test]
+async fn updates_unchanged_page_of_another_token_owner() {
+    let server = MockConfluence::start().await;
+    server.add_page("100", "Runbook");
+
+    let workspace = Workspace::new();
+    workspace.write("confluence-updater.yaml", CONFIG);
+    workspace.write("README.md", "# Runbook\n");
+    workspace.run_ok(&server, &[]).await;
+
+    // The page was published by another bot account before.
+    for label in server
+        .state()
+        .pages
+        .get_mut("100")
+        .unwrap()
+        .labels
+        .iter_mut()
+    {
+        if label.starts_with("pa-token/") {
+            *label = "pa-token/old-bot".to_string();
+        }
+    }
+
+    workspace.run_ok(&server, &[]).await;
+
+    let labels = server.page("100").labels;
+    assert_eq!(server.count(Method::PUT, "/rest/api/content/100"), 2);
+    assert!(
+        labels.contains(&"pa-token/test-user".to_string()),
+        "{labels:?}"
+    );
+    assert!(
+        !labels.contains(&"pa-token/old-bot".to_string()),
+        "{labels:?}"
+    );
+}